@@ -0,0 +1,58 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use keccak_hash::keccak;
+use native::signature_verification::{AuthorizedSigner, SignatureVerifier};
+use secp256k1::{recovery::RecoverableSignature, Message, PublicKey, Secp256k1, SecretKey};
+
+const BATCH_SIZES: [usize; 3] = [16, 256, 4096];
+
+/// Signed receipts shaped like allocation receipts: 67 bytes of receipt data
+fn signed_receipts(count: usize) -> ([u8; 20], Vec<(Vec<u8>, RecoverableSignature)>) {
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+
+    let public_key = PublicKey::from_secret_key(&secp, &secret_key).serialize_uncompressed();
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&keccak(&public_key[1..])[12..]);
+
+    let receipts = (0..count)
+        .map(|i| {
+            let mut receipt = vec![0u8; 67];
+            receipt[..8].copy_from_slice(&(i as u64).to_be_bytes());
+            let message = Message::from_slice(keccak(&receipt).as_bytes()).unwrap();
+            let signature = secp.sign_recoverable(&message, &secret_key);
+            (receipt, signature)
+        })
+        .collect();
+    (address, receipts)
+}
+
+fn verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verify");
+    for size in BATCH_SIZES {
+        let (address, receipts) = signed_receipts(size);
+        let verifier = SignatureVerifier::new(vec![AuthorizedSigner::new(address)]);
+        group.throughput(Throughput::Elements(size as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("single", size),
+            &receipts,
+            |b, receipts| {
+                b.iter(|| {
+                    for (message, signature) in receipts {
+                        assert_eq!(verifier.verify(message, signature), Ok(true));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("batch", size), &receipts, |b, receipts| {
+            b.iter(|| {
+                let results = verifier.verify_batch(receipts);
+                assert!(results.iter().all(|result| result == &Ok(Some(address))));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, verify);
+criterion_main!(benches);
@@ -1,9 +1,22 @@
 use super::*;
 use eip_712_derive::{
-    sign_typed, Bytes32, DomainSeparator, Eip712Domain, MemberVisitor, StructType, U256,
+    hash_struct, sign_typed, Bytes32, DomainSeparator, Eip712Domain, MemberVisitor, StructType,
+    U256,
 };
-use secp256k1::SecretKey;
-use std::convert::TryInto;
+use secp256k1::{recovery::RecoveryId, SecretKey};
+use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+/// Default EIP-712 domain name of the Graph Protocol attestations
+pub const DOMAIN_NAME: &str = "Graph Protocol";
+/// Default EIP-712 domain version of the Graph Protocol attestations
+pub const DOMAIN_VERSION: &str = "0";
+/// Default EIP-712 domain salt of the Graph Protocol attestations
+pub const DOMAIN_SALT: &str = "a070ffb1cd7409649bf77822cce74495468e06dbfaef09556838bf188679b9c2";
+
+/// Size of an attestation in the encoding expected by the DisputeManager contract
+pub const ATTESTATION_SIZE_BYTES: usize = 161;
 
 pub struct AttestationSigner {
     subgraph_deployment_id: Bytes32,
@@ -13,24 +26,11 @@ pub struct AttestationSigner {
 
 impl AttestationSigner {
     pub fn new(
-        chain_id: U256,
-        dispute_manager: Address,
+        domain: &AttestationDomain,
         signer: SecretKey,
         subgraph_deployment_id: Bytes32,
     ) -> Self {
-        let bytes = hex::decode("a070ffb1cd7409649bf77822cce74495468e06dbfaef09556838bf188679b9c2")
-            .unwrap();
-
-        let salt: [u8; 32] = bytes.try_into().unwrap();
-
-        let domain = Eip712Domain {
-            name: "Graph Protocol".to_owned(),
-            version: "0".to_owned(),
-            chain_id,
-            verifying_contract: eip_712_derive::Address(dispute_manager),
-            salt,
-        };
-        let domain_separator = DomainSeparator::new(&domain);
+        let domain_separator = DomainSeparator::new(&domain.eip712_domain());
 
         Self {
             domain_separator,
@@ -39,6 +39,7 @@ impl AttestationSigner {
         }
     }
 
+    #[tracing::instrument(name = "attestation_signing", skip_all)]
     pub fn create_attestation(&self, request: &str, response: &str) -> Attestation {
         let request_cid = keccak(request).to_fixed_bytes();
         let response_cid = keccak(response).to_fixed_bytes();
@@ -82,7 +83,8 @@ impl StructType for Receipt {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "AttestationJson", try_from = "AttestationJson")]
 pub struct Attestation {
     pub request_cid: Bytes32,
     pub response_cid: Bytes32,
@@ -91,3 +93,271 @@ pub struct Attestation {
     pub r: Bytes32,
     pub s: Bytes32,
 }
+
+impl Attestation {
+    /// Encode as `requestCID || responseCID || subgraphDeploymentID || r || s || v`, the
+    /// layout parsed by the DisputeManager contract
+    pub fn to_bytes(&self) -> [u8; ATTESTATION_SIZE_BYTES] {
+        let mut bytes = [0u8; ATTESTATION_SIZE_BYTES];
+        bytes[0..32].copy_from_slice(&self.request_cid);
+        bytes[32..64].copy_from_slice(&self.response_cid);
+        bytes[64..96].copy_from_slice(&self.subgraph_deployment_id);
+        bytes[96..128].copy_from_slice(&self.r);
+        bytes[128..160].copy_from_slice(&self.s);
+        bytes[160] = self.v;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AttestationError> {
+        if bytes.len() != ATTESTATION_SIZE_BYTES {
+            return Err(AttestationError::InvalidLength(bytes.len()));
+        }
+        // Unwraps: the slices have the length of the arrays
+        Ok(Attestation {
+            request_cid: bytes[0..32].try_into().unwrap(),
+            response_cid: bytes[32..64].try_into().unwrap(),
+            subgraph_deployment_id: bytes[64..96].try_into().unwrap(),
+            r: bytes[96..128].try_into().unwrap(),
+            s: bytes[128..160].try_into().unwrap(),
+            v: bytes[160],
+        })
+    }
+}
+
+/// JSON representation of an attestation, as sent to gateways
+#[derive(Serialize, Deserialize)]
+struct AttestationJson {
+    #[serde(rename = "requestCID")]
+    request_cid: String,
+    #[serde(rename = "responseCID")]
+    response_cid: String,
+    #[serde(rename = "subgraphDeploymentID")]
+    subgraph_deployment_id: String,
+    v: u8,
+    r: String,
+    s: String,
+}
+
+impl From<Attestation> for AttestationJson {
+    fn from(attestation: Attestation) -> Self {
+        AttestationJson {
+            request_cid: format!("0x{}", hex::encode(attestation.request_cid)),
+            response_cid: format!("0x{}", hex::encode(attestation.response_cid)),
+            subgraph_deployment_id: format!(
+                "0x{}",
+                hex::encode(attestation.subgraph_deployment_id)
+            ),
+            v: attestation.v,
+            r: format!("0x{}", hex::encode(attestation.r)),
+            s: format!("0x{}", hex::encode(attestation.s)),
+        }
+    }
+}
+
+impl TryFrom<AttestationJson> for Attestation {
+    type Error = AttestationError;
+
+    fn try_from(json: AttestationJson) -> Result<Self, Self::Error> {
+        Ok(Attestation {
+            request_cid: parse_bytes32(&json.request_cid)?,
+            response_cid: parse_bytes32(&json.response_cid)?,
+            subgraph_deployment_id: parse_bytes32(&json.subgraph_deployment_id)?,
+            v: json.v,
+            r: parse_bytes32(&json.r)?,
+            s: parse_bytes32(&json.s)?,
+        })
+    }
+}
+
+fn parse_bytes32(value: &str) -> Result<Bytes32, AttestationError> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))
+        .map_err(|_| AttestationError::InvalidHex(value.to_string()))?;
+    bytes
+        .try_into()
+        .map_err(|_| AttestationError::InvalidHex(value.to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    InvalidLength(usize),
+    InvalidHex(String),
+    InvalidRecoveryId(u8),
+    InvalidSignature,
+    RequestMismatch,
+    ResponseMismatch,
+}
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidLength(len) => write!(
+                f,
+                "Attestation must be {} bytes, got {}",
+                ATTESTATION_SIZE_BYTES, len
+            ),
+            Self::InvalidHex(value) => write!(f, "Invalid 32 bytes hex value: {}", value),
+            Self::InvalidRecoveryId(v) => write!(f, "Invalid signature recovery id: {}", v),
+            Self::InvalidSignature => write!(f, "Failed to recover the attestation signer"),
+            Self::RequestMismatch => write!(f, "Request does not match the attestation"),
+            Self::ResponseMismatch => write!(f, "Response does not match the attestation"),
+        }
+    }
+}
+
+impl std::error::Error for AttestationError {}
+
+/// EIP-712 domain that attestations are signed for, verified by the DisputeManager
+/// contract of the network
+#[derive(Debug, Clone)]
+pub struct AttestationDomain {
+    pub name: String,
+    pub version: String,
+    pub chain_id: U256,
+    pub dispute_manager: Address,
+    pub salt: Bytes32,
+}
+
+impl AttestationDomain {
+    /// Domain of the Graph Protocol attestations on a chain
+    pub fn new(chain_id: u64, dispute_manager: Address) -> Self {
+        let mut chain_id_bytes = [0u8; 32];
+        chain_id_bytes[24..].copy_from_slice(&chain_id.to_be_bytes());
+        Self {
+            name: DOMAIN_NAME.to_owned(),
+            version: DOMAIN_VERSION.to_owned(),
+            chain_id: U256(chain_id_bytes),
+            dispute_manager,
+            // Unwraps: the default salt is a valid 32 bytes hex string
+            salt: hex::decode(DOMAIN_SALT).unwrap().try_into().unwrap(),
+        }
+    }
+
+    fn eip712_domain(&self) -> Eip712Domain {
+        Eip712Domain {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            chain_id: self.chain_id,
+            verifying_contract: eip_712_derive::Address(self.dispute_manager),
+            salt: self.salt,
+        }
+    }
+
+    /// EIP-712 hash of the receipt signed by an attestation, hashed the same way as
+    /// `sign_typed` does with the `DomainSeparator` of the domain
+    fn receipt_hash(&self, attestation: &Attestation) -> Bytes32 {
+        let receipt = Receipt {
+            request_cid: attestation.request_cid,
+            response_cid: attestation.response_cid,
+            subgraph_deployment_id: attestation.subgraph_deployment_id,
+        };
+
+        let mut message = Vec::with_capacity(2 + 2 * 32);
+        message.extend_from_slice(&[0x19, 0x01]);
+        message.extend_from_slice(&hash_struct(&self.eip712_domain()));
+        message.extend_from_slice(&hash_struct(&receipt));
+        keccak(message).to_fixed_bytes()
+    }
+}
+
+/// Recover the address that signed an attestation for the given domain
+pub fn recover_signer(
+    domain: &AttestationDomain,
+    attestation: &Attestation,
+) -> Result<Address, AttestationError> {
+    // Accept both the Ethereum (27/28) and the raw (0/1) recovery ids
+    let recovery_id = match attestation.v {
+        27 | 28 => attestation.v - 27,
+        v => v,
+    };
+    let recovery_id = RecoveryId::from_i32(recovery_id as i32)
+        .map_err(|_| AttestationError::InvalidRecoveryId(attestation.v))?;
+
+    let mut rs = [0u8; 64];
+    rs[0..32].copy_from_slice(&attestation.r);
+    rs[32..64].copy_from_slice(&attestation.s);
+    let signature = RecoverableSignature::from_compact(&rs, recovery_id)
+        .map_err(|_| AttestationError::InvalidSignature)?;
+
+    let message = Message::from_slice(&domain.receipt_hash(attestation)).unwrap();
+    let signer = SECP256K1
+        .recover(&message, &signature)
+        .map_err(|_| AttestationError::InvalidSignature)?;
+    Ok(public_key_address(&signer))
+}
+
+/// Check that an attestation covers the given request and response, and return its signer
+pub fn verify_attestation(
+    domain: &AttestationDomain,
+    attestation: &Attestation,
+    request: &str,
+    response: &str,
+) -> Result<Address, AttestationError> {
+    if keccak(request).to_fixed_bytes() != attestation.request_cid {
+        return Err(AttestationError::RequestMismatch);
+    }
+    if keccak(response).to_fixed_bytes() != attestation.response_cid {
+        return Err(AttestationError::ResponseMismatch);
+    }
+    recover_signer(domain, attestation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = r#"{"query":"{ _meta { block { number } } }","variables":null}"#;
+    const RESPONSE: &str = r#"{"data":{"_meta":{"block":{"number":42}}}}"#;
+
+    fn domain() -> AttestationDomain {
+        AttestationDomain::new(1, [0x42; 20])
+    }
+
+    fn signer_key() -> (SecretKey, Address) {
+        let key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &key);
+        (key, public_key_address(&public_key))
+    }
+
+    #[test]
+    fn created_attestations_verify_and_round_trip() {
+        let (key, address) = signer_key();
+        let signer = AttestationSigner::new(&domain(), key, [0x07; 32]);
+        let attestation = signer.create_attestation(REQUEST, RESPONSE);
+
+        assert_eq!(recover_signer(&domain(), &attestation), Ok(address));
+        assert_eq!(
+            verify_attestation(&domain(), &attestation, REQUEST, RESPONSE),
+            Ok(address)
+        );
+
+        let decoded = Attestation::from_bytes(&attestation.to_bytes()).unwrap();
+        assert_eq!(decoded, attestation);
+        assert_eq!(
+            verify_attestation(&domain(), &decoded, REQUEST, RESPONSE),
+            Ok(address)
+        );
+    }
+
+    #[test]
+    fn verification_rejects_other_requests_and_domains() {
+        let (key, address) = signer_key();
+        let signer = AttestationSigner::new(&domain(), key, [0x07; 32]);
+        let attestation = signer.create_attestation(REQUEST, RESPONSE);
+
+        assert_eq!(
+            verify_attestation(&domain(), &attestation, "{}", RESPONSE),
+            Err(AttestationError::RequestMismatch)
+        );
+        assert_eq!(
+            verify_attestation(&domain(), &attestation, REQUEST, "{}"),
+            Err(AttestationError::ResponseMismatch)
+        );
+        // Signed for another DisputeManager, the recovered signer is a different address
+        let other_domain = AttestationDomain::new(1, [0x43; 20]);
+        assert_ne!(recover_signer(&other_domain, &attestation), Ok(address));
+        assert_eq!(
+            Attestation::from_bytes(&attestation.to_bytes()[1..]),
+            Err(AttestationError::InvalidLength(ATTESTATION_SIZE_BYTES - 1))
+        );
+    }
+}
@@ -7,4 +7,18 @@ use std::sync::Arc;
 pub mod attestation;
 pub mod signature_verification;
 
-type Address = [u8; 20];
+pub type Address = [u8; 20];
+
+lazy_static! {
+    static ref SECP256K1: Secp256k1<VerifyOnly> = Secp256k1::verification_only();
+}
+
+/// Ethereum address of a public key
+fn public_key_address(public_key: &PublicKey) -> Address {
+    let ser = public_key.serialize_uncompressed();
+    debug_assert_eq!(ser[0], 0x04);
+    let pk_hash = keccak(&ser[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&pk_hash[12..]);
+    address
+}
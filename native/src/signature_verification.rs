@@ -1,59 +1,259 @@
 use super::*;
+use arc_swap::ArcSwapOption;
+use rayon::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-lazy_static! {
-    static ref SECP256K1: Secp256k1<VerifyOnly> = Secp256k1::verification_only();
+/// A signer trusted to sign receipts, optionally only within a validity window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizedSigner {
+    pub address: Address,
+    /// Unix timestamp (in seconds) from which signatures are accepted
+    pub valid_from: Option<u64>,
+    /// Unix timestamp (in seconds) until which signatures are accepted
+    pub valid_until: Option<u64>,
 }
 
-enum Signer {
-    PublicKey(PublicKey),
-    Address(Address),
+impl AuthorizedSigner {
+    /// A signer accepted at any time
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            valid_from: None,
+            valid_until: None,
+        }
+    }
+
+    pub fn is_valid_at(&self, timestamp: u64) -> bool {
+        self.valid_from.is_none_or(|from| from <= timestamp)
+            && self.valid_until.is_none_or(|until| timestamp < until)
+    }
+}
+
+struct Signer {
+    signer: AuthorizedSigner,
+    // Derived from the first signature matching the address, so that later verifications
+    // can skip keccak and use the much faster verify method instead of recover.
+    public_key: ArcSwapOption<PublicKey>,
 }
 
 impl SignatureVerifier {
-    pub fn new(signer: Address) -> Self {
+    pub fn new(signers: Vec<AuthorizedSigner>) -> Self {
         Self {
-            signer: ArcSwap::from_pointee(Signer::Address(signer)),
+            signers: ArcSwap::from_pointee(Self::entries(signers, &[])),
         }
     }
 
+    /// Replace the set of authorized signers, keeping the public keys already derived for
+    /// signers that remain authorized
+    pub fn set_signers(&self, signers: Vec<AuthorizedSigner>) {
+        self.signers
+            .rcu(|current| Self::entries(signers.clone(), current));
+    }
+
+    pub fn signers(&self) -> Vec<AuthorizedSigner> {
+        self.signers
+            .load()
+            .iter()
+            .map(|entry| entry.signer.clone())
+            .collect()
+    }
+
+    fn entries(signers: Vec<AuthorizedSigner>, current: &[Signer]) -> Vec<Signer> {
+        signers
+            .into_iter()
+            .map(|signer| {
+                let public_key = current
+                    .iter()
+                    .find(|entry| entry.signer.address == signer.address)
+                    .and_then(|entry| entry.public_key.load_full());
+                Signer {
+                    signer,
+                    public_key: ArcSwapOption::new(public_key),
+                }
+            })
+            .collect()
+    }
+
+    /// Verify a signature against the authorized signers
     pub fn verify(
         &self,
         message: &[u8],
         signature: &RecoverableSignature,
     ) -> Result<bool, &'static str> {
+        self.verify_signer(message, signature)
+            .map(|signer| signer.is_some())
+    }
+
+    /// Verify a signature against the authorized signers, returning the address of the
+    /// matching signer if there is one
+    pub fn verify_signer(
+        &self,
+        message: &[u8],
+        signature: &RecoverableSignature,
+    ) -> Result<Option<Address>, &'static str> {
+        self.verify_at(message, signature, unix_timestamp())
+    }
+
+    fn verify_at(
+        &self,
+        message: &[u8],
+        signature: &RecoverableSignature,
+        timestamp: u64,
+    ) -> Result<Option<Address>, &'static str> {
         let message = Message::from_slice(&keccak(message).to_fixed_bytes()).unwrap();
+        let signers = self.signers.load();
+        let mut valid = signers
+            .iter()
+            .filter(|entry| entry.signer.is_valid_at(timestamp));
 
-        match self.signer.load().as_ref() {
-            // If we already have the public key we can do the fast path.
-            Signer::PublicKey(signer) => Ok(SECP256K1
-                .verify(&message, &signature.to_standard(), signer)
-                .is_ok()),
-            // If we don't have the public key, but have the address instead
-            // we derive the address from the recovered key. If it's a match
-            // then we can save the public key for the next time avoiding
-            // running keccak on every verification and using the much faster
-            // verify method instead of the slow recover method.
-            Signer::Address(addr) => {
-                let recovered_signer = SECP256K1
-                    .recover(&message, signature)
-                    .map_err(|_| "Failed to recover signature")?;
-
-                let ser = recovered_signer.serialize_uncompressed();
-                debug_assert_eq!(ser[0], 0x04);
-                let pk_hash = keccak(&ser[1..]);
-                let equal = &pk_hash[12..] == addr;
-
-                if equal {
-                    self.signer
-                        .store(Arc::new(Signer::PublicKey(recovered_signer)))
-                }
+        // With a single signer whose public key is known we can do the fast path.
+        if let (Some(entry), None) = (valid.next(), valid.next()) {
+            if let Some(public_key) = entry.public_key.load().as_ref() {
+                let equal = SECP256K1
+                    .verify(&message, &signature.to_standard(), public_key)
+                    .is_ok();
+                return Ok(if equal {
+                    Some(entry.signer.address)
+                } else {
+                    None
+                });
+            }
+        }
+
+        let recovered_signer = SECP256K1
+            .recover(&message, signature)
+            .map_err(|_| "Failed to recover signature")?;
+        let valid = || {
+            signers
+                .iter()
+                .filter(|entry| entry.signer.is_valid_at(timestamp))
+        };
+
+        // Compare with the known public keys first, to avoid hashing the recovered key
+        if let Some(entry) = valid().find(|entry| {
+            entry
+                .public_key
+                .load()
+                .as_ref()
+                .is_some_and(|public_key| public_key.as_ref() == &recovered_signer)
+        }) {
+            return Ok(Some(entry.signer.address));
+        }
 
-                Ok(equal)
+        let address = public_key_address(&recovered_signer);
+        match valid().find(|entry| entry.signer.address == address) {
+            Some(entry) => {
+                entry.public_key.store(Some(Arc::new(recovered_signer)));
+                Ok(Some(address))
             }
+            None => Ok(None),
         }
     }
+
+    /// Verify many (message, signature) pairs like `verify_signer`, spreading the hashing and
+    /// signature checks over the rayon thread pool. Results are returned in the order of
+    /// `items`.
+    pub fn verify_batch<M: AsRef<[u8]> + Sync>(
+        &self,
+        items: &[(M, RecoverableSignature)],
+    ) -> Vec<Result<Option<Address>, &'static str>> {
+        let (first, rest) = match items.split_first() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+
+        // Verifying the first pair on its own resolves the public key when only the
+        // address is known, so the rest of the batch can take the fast path.
+        let timestamp = unix_timestamp();
+        let mut results = Vec::with_capacity(items.len());
+        results.push(self.verify_at(first.0.as_ref(), &first.1, timestamp));
+        results
+            .par_extend(rest.par_iter().map(|(message, signature)| {
+                self.verify_at(message.as_ref(), signature, timestamp)
+            }));
+        results
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
 }
 
 pub struct SignatureVerifier {
-    signer: ArcSwap<Signer>,
+    signers: ArcSwap<Vec<Signer>>,
+}
+
+impl std::fmt::Debug for SignatureVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SignatureVerifier")
+            .field("signers", &self.signers())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::SecretKey;
+
+    fn signer(key: &SecretKey) -> Address {
+        public_key_address(&PublicKey::from_secret_key(&Secp256k1::signing_only(), key))
+    }
+
+    fn sign(key: &SecretKey, message: &[u8]) -> RecoverableSignature {
+        let message = Message::from_slice(&keccak(message).to_fixed_bytes()).unwrap();
+        Secp256k1::signing_only().sign_recoverable(&message, key)
+    }
+
+    #[test]
+    fn batch_and_single_verification_agree() {
+        let authorized = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let expired = SecretKey::from_slice(&[0x22; 32]).unwrap();
+        let unknown = SecretKey::from_slice(&[0x33; 32]).unwrap();
+        let signers = vec![
+            AuthorizedSigner::new(signer(&authorized)),
+            AuthorizedSigner {
+                address: signer(&expired),
+                valid_from: None,
+                valid_until: Some(1),
+            },
+        ];
+
+        let items: Vec<(Vec<u8>, RecoverableSignature)> = (0u8..8)
+            .map(|i| {
+                let message = vec![i; 67];
+                let key = match i % 4 {
+                    1 => &expired,
+                    2 => &unknown,
+                    _ => &authorized,
+                };
+                let signature = sign(key, &message);
+                (message, signature)
+            })
+            .collect();
+
+        // Separate verifiers, as both paths remember the public keys they derive
+        let batch = SignatureVerifier::new(signers.clone()).verify_batch(&items);
+        let single = SignatureVerifier::new(signers);
+        let expected: Vec<_> = items
+            .iter()
+            .map(|(message, signature)| single.verify_signer(message, signature))
+            .collect();
+        assert_eq!(batch, expected);
+
+        for (i, ((message, signature), result)) in items.iter().zip(&batch).enumerate() {
+            // Signatures of the expired and the unknown signers are rejected
+            let accepted = i % 4 != 1 && i % 4 != 2;
+            let matched = if accepted {
+                Some(signer(&authorized))
+            } else {
+                None
+            };
+            assert_eq!(result, &Ok(matched));
+            assert_eq!(single.verify(message, signature), Ok(accepted));
+        }
+    }
 }
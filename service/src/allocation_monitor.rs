@@ -0,0 +1,113 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use ethers::types::Address;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::{
+    common::indexer_error::{IndexerError, IndexerErrorCode},
+    network_subgraph::NetworkSubgraphClient,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubgraphDeployment {
+    pub id: String,
+}
+
+/// Active allocation of the indexer
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Allocation {
+    pub id: String,
+    pub subgraph_deployment: SubgraphDeployment,
+    pub allocated_tokens: String,
+    pub created_at_epoch: u64,
+}
+
+/// Active allocations of the indexer, synced from the network subgraph
+#[derive(Debug, Clone)]
+pub struct AllocationMonitor {
+    network_subgraph: NetworkSubgraphClient,
+    indexer: Address,
+    allocations: Arc<RwLock<HashMap<String, Allocation>>>,
+}
+
+impl AllocationMonitor {
+    pub fn new(network_subgraph: NetworkSubgraphClient, indexer: Address) -> Self {
+        AllocationMonitor {
+            network_subgraph,
+            indexer,
+            allocations: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Active allocations by allocation ID
+    pub fn allocations(&self) -> HashMap<String, Allocation> {
+        self.allocations.read().unwrap().clone()
+    }
+
+    /// Active allocation with the given ID
+    pub fn allocation(&self, id: &str) -> Option<Allocation> {
+        self.allocations.read().unwrap().get(id).cloned()
+    }
+
+    pub async fn sync(&self) -> Result<(), IndexerError> {
+        let query = json!({
+            "query": "query allocations($indexer: String!) { indexer(id: $indexer) { activeAllocations: totalAllocations(where: { status: Active }, orderDirection: desc, first: 1000) { id allocatedTokens createdAtEpoch subgraphDeployment { id } } } }",
+            "variables": { "indexer": format!("{:?}", self.indexer) },
+        });
+        let response = self
+            .network_subgraph
+            .query(query.to_string())
+            .await
+            .map_err(|e| IndexerError::new(IndexerErrorCode::IE010, Some(e.to_string().into())))?;
+
+        let allocations = serde_json::from_str::<Value>(&response.graphql_response)
+            .ok()
+            .and_then(|mut response| {
+                let allocations = response
+                    .pointer_mut("/data/indexer/activeAllocations")
+                    .map(Value::take)
+                    // An indexer unknown to the network has no allocations
+                    .unwrap_or(Value::Array(Vec::new()));
+                serde_json::from_value::<Vec<Allocation>>(allocations).ok()
+            })
+            .ok_or_else(|| {
+                IndexerError::new(
+                    IndexerErrorCode::IE010,
+                    Some(
+                        format!(
+                            "Unexpected allocations response: {}",
+                            response.graphql_response
+                        )
+                        .into(),
+                    ),
+                )
+            })?;
+
+        let mut current = self.allocations.write().unwrap();
+        if current.len() != allocations.len() {
+            info!(allocations = allocations.len(), "Synced active allocations");
+        }
+        *current = allocations
+            .into_iter()
+            .map(|allocation| (allocation.id.clone(), allocation))
+            .collect();
+        Ok(())
+    }
+
+    pub async fn monitor(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            // Failures are logged as the indexer error is built
+            let _ = self.sync().await;
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use ethers_core::{types::Address, utils::hex};
+use native::attestation::{AttestationDomain, AttestationSigner};
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    allocation_monitor::Allocation,
+    common::{
+        indexer_error::{IndexerError, IndexerErrorCode},
+        signer::OperatorSigner,
+    },
+    query_processor::SubgraphDeploymentID,
+};
+
+/// Derivation indices tried for each epoch, as used by the indexer agent when allocating
+const MAX_ALLOCATION_INDEX: u32 = 100;
+
+/// Attestation signers of the indexer allocations, derived from the operator key on first use
+#[derive(Clone)]
+pub struct AttestationSigners {
+    operator_signer: Arc<dyn OperatorSigner>,
+    domain: AttestationDomain,
+    signers: Arc<RwLock<HashMap<String, Arc<AttestationSigner>>>>,
+}
+
+impl fmt::Debug for AttestationSigners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttestationSigners")
+            .field("domain", &self.domain)
+            .field("allocations", &self.signers.read().unwrap().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AttestationSigners {
+    pub fn new(operator_signer: Arc<dyn OperatorSigner>, domain: AttestationDomain) -> Self {
+        AttestationSigners {
+            operator_signer,
+            domain,
+            signers: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Signer of the attestations for queries paid to an allocation
+    pub async fn signer(
+        &self,
+        allocation: &Allocation,
+    ) -> Result<Arc<AttestationSigner>, IndexerError> {
+        if let Some(signer) = self.signers.read().unwrap().get(&allocation.id) {
+            return Ok(signer.clone());
+        }
+
+        let error = |e: String| IndexerError::new(IndexerErrorCode::IE022, Some(e.into()));
+        let deployment: [u8; 32] =
+            SubgraphDeploymentID::new(allocation.subgraph_deployment.id.clone())
+                .bytes32()
+                .and_then(|bytes32| hex::decode(bytes32.trim_start_matches("0x")).ok())
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| {
+                    error(format!(
+                        "Invalid deployment {}",
+                        allocation.subgraph_deployment.id
+                    ))
+                })?;
+
+        // Key derivation is CPU bound, and up to a few hundred keys may be tried
+        let operator_signer = self.operator_signer.clone();
+        let derived_allocation = allocation.clone();
+        let key = tokio::task::spawn_blocking(move || {
+            allocation_key(operator_signer.as_ref(), &derived_allocation)
+        })
+        .await
+        .map_err(|e| error(e.to_string()))?
+        .map_err(error)?;
+
+        let signer = Arc::new(AttestationSigner::new(&self.domain, key, deployment));
+        self.signers
+            .write()
+            .unwrap()
+            .insert(allocation.id.clone(), signer.clone());
+        Ok(signer)
+    }
+}
+
+/// Key of an allocation, whose address is the allocation ID. Allocations are created with a
+/// key derived for the current epoch, or the previous one if the epoch changed meanwhile.
+fn allocation_key(
+    operator_signer: &dyn OperatorSigner,
+    allocation: &Allocation,
+) -> Result<SecretKey, String> {
+    let ipfs_hash = SubgraphDeploymentID::new(allocation.subgraph_deployment.id.clone())
+        .ipfs_hash()
+        .ok_or_else(|| format!("Invalid deployment {}", allocation.subgraph_deployment.id))?;
+    let allocation_id = allocation
+        .id
+        .parse::<Address>()
+        .map_err(|e| format!("Invalid allocation ID {}: {}", allocation.id, e))?;
+
+    let epochs = [
+        allocation.created_at_epoch,
+        allocation.created_at_epoch.saturating_sub(1),
+    ];
+    for epoch in epochs {
+        for index in 0..MAX_ALLOCATION_INDEX {
+            let key = operator_signer
+                .attestation_key(epoch, &ipfs_hash, index)
+                .map_err(|e| e.to_string())?;
+            if key_address(&key) == allocation_id {
+                return Ok(key);
+            }
+        }
+    }
+    Err(format!(
+        "No key derived from the operator matches allocation {}",
+        allocation.id
+    ))
+}
+
+fn key_address(key: &SecretKey) -> Address {
+    let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), key);
+    let hash = Keccak256::digest(&public_key.serialize_uncompressed()[1..]);
+    Address::from_slice(&hash[12..])
+}
+
+#[cfg(test)]
+mod tests {
+    use native::attestation::verify_attestation;
+
+    use super::*;
+    use crate::{allocation_monitor::SubgraphDeployment, common::signer::LocalSigner};
+
+    const MNEMONIC: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const DEPLOYMENT: &str = "QmVhiE4nax9i86UBnBmQCYDzvjWuwHShYh7aspGPQhU5Sj";
+
+    #[tokio::test]
+    async fn allocations_sign_with_the_key_derived_for_them() {
+        let operator_signer = LocalSigner::from_secret(MNEMONIC).unwrap();
+        // Allocated right before the epoch changed
+        let key = operator_signer.attestation_key(9, DEPLOYMENT, 2).unwrap();
+        let allocation_id = key_address(&key);
+        let deployment = SubgraphDeploymentID::new(DEPLOYMENT.to_string());
+        let allocation = Allocation {
+            id: format!("{:?}", allocation_id),
+            subgraph_deployment: SubgraphDeployment {
+                id: deployment.bytes32().unwrap(),
+            },
+            allocated_tokens: "0".to_string(),
+            created_at_epoch: 10,
+        };
+        assert_eq!(
+            SubgraphDeploymentID::new(allocation.subgraph_deployment.id.clone()).ipfs_hash(),
+            Some(DEPLOYMENT.to_string())
+        );
+
+        let domain = AttestationDomain::new(1, [0x42; 20]);
+        let signers = AttestationSigners::new(Arc::new(operator_signer), domain.clone());
+        let signer = signers.signer(&allocation).await.unwrap();
+        let attestation = signer.create_attestation("request", "response");
+        assert_eq!(
+            verify_attestation(&domain, &attestation, "request", "response"),
+            Ok(allocation_id.to_fixed_bytes())
+        );
+        assert!(Arc::ptr_eq(
+            &signer,
+            &signers.signer(&allocation).await.unwrap()
+        ));
+    }
+}
@@ -0,0 +1,446 @@
+use std::time::Duration;
+
+use ethers::providers::Middleware;
+use ethers_core::types::Address;
+use native::attestation::AttestationDomain;
+use reqwest::{header, Client};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::{
+    config::Ethereum,
+    readiness::{Dependency, Readiness},
+};
+
+/// Delays between attempts to resolve the chain context, doubled after each failure
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Protocol chains with known contract deployments
+pub struct KnownChain {
+    pub name: &'static str,
+    pub chain_id: u64,
+    pub dispute_manager: &'static str,
+    pub staking: &'static str,
+}
+
+pub const KNOWN_CHAINS: &[KnownChain] = &[
+    KnownChain {
+        name: "mainnet",
+        chain_id: 1,
+        dispute_manager: "0x97307b963662cCA2f7eD50e38dCC555dfFc4FB0b",
+        staking: "0xF55041E37E12cD407ad00CE2910B8269B01263b9",
+    },
+    KnownChain {
+        name: "goerli",
+        chain_id: 5,
+        dispute_manager: "0x8c344366D9269174F10bB588F16945eb47f78dc9",
+        staking: "0x35e3Cb6B317690d662160d5d02A5b364578F62c9",
+    },
+    KnownChain {
+        name: "arbitrum-one",
+        chain_id: 42161,
+        dispute_manager: "0x0Ab2B043138352413Bb02e67E626a70320E3BD46",
+        staking: "0x00669A4CF01450B64E8A2A20E9b1FCB71E61eF03",
+    },
+    KnownChain {
+        name: "arbitrum-goerli",
+        chain_id: 421613,
+        dispute_manager: "0x16DEF7E0108A5467A106dbD7537f8591f470342E",
+        staking: "0xcd549d0C43d915aEB21d3a331dEaB9B7aF186D26",
+    },
+];
+
+impl KnownChain {
+    pub fn from_chain_id(chain_id: u64) -> Option<&'static KnownChain> {
+        KNOWN_CHAINS.iter().find(|chain| chain.chain_id == chain_id)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChainError {
+    #[error("Failed to fetch the chain ID from the Ethereum provider: {0}")]
+    Provider(String),
+    #[error("Configured chain ID {configured} does not match chain ID {provider} of the Ethereum provider")]
+    ChainIdMismatch { configured: u64, provider: u64 },
+    #[error("Invalid contract address: {0}")]
+    InvalidAddress(String),
+    #[error("Failed to fetch contract addresses from the network subgraph: {0}")]
+    NetworkSubgraph(String),
+}
+
+impl ChainError {
+    /// Errors caused by an unreachable dependency rather than by the configuration, which
+    /// may resolve on retry
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ChainError::Provider(_) | ChainError::NetworkSubgraph(_)
+        )
+    }
+}
+
+/// Chain ID and contract addresses of the protocol network
+#[derive(Debug, Clone)]
+pub struct ChainContext {
+    pub chain_id: u64,
+    pub attestation_domain: AttestationDomain,
+    pub staking_address: Address,
+}
+
+/// Resolve the chain context, retrying with backoff while the Ethereum provider or the
+/// network subgraph are unreachable. The chain is reported not ready until it resolves,
+/// and configuration errors are returned right away.
+pub async fn resolve_chain_context<M: Middleware>(
+    config: &Ethereum,
+    provider: &M,
+    network_subgraph_endpoint: &str,
+    network_subgraph_auth_token: Option<&str>,
+    readiness: &Readiness,
+) -> Result<ChainContext, ChainError> {
+    let mut delay = INITIAL_RETRY_DELAY;
+    loop {
+        let resolved: Result<ChainContext, ChainError> = async {
+            let chain_id = resolve_chain_id(config, provider).await?;
+            // Attestations are signed for the DisputeManager of the chain
+            let attestation_domain = resolve_attestation_domain(
+                config,
+                chain_id,
+                network_subgraph_endpoint,
+                network_subgraph_auth_token,
+            )
+            .await?;
+            let staking_address = resolve_staking_address(
+                config,
+                chain_id,
+                network_subgraph_endpoint,
+                network_subgraph_auth_token,
+            )
+            .await?;
+            Ok(ChainContext {
+                chain_id,
+                attestation_domain,
+                staking_address,
+            })
+        }
+        .await;
+
+        match resolved {
+            Ok(context) => {
+                readiness.record(Dependency::Chain, Ok(())).await;
+                return Ok(context);
+            }
+            Err(e) => {
+                readiness
+                    .record(Dependency::Chain, Err(e.to_string()))
+                    .await;
+                if !e.is_transient() {
+                    return Err(e);
+                }
+                warn!(
+                    error = %e,
+                    retry_in_ms = delay.as_millis() as u64,
+                    "Failed to resolve the chain context, retrying"
+                );
+            }
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+    }
+}
+
+/// Chain ID of the protocol network, as reported by the provider. A configured chain ID
+/// must match it.
+pub async fn resolve_chain_id<M: Middleware>(
+    config: &Ethereum,
+    provider: &M,
+) -> Result<u64, ChainError> {
+    let provider_chain_id = provider
+        .get_chainid()
+        .await
+        .map_err(|e| ChainError::Provider(e.to_string()))?
+        .as_u64();
+    match config.chain_id {
+        Some(configured) if configured != provider_chain_id => Err(ChainError::ChainIdMismatch {
+            configured,
+            provider: provider_chain_id,
+        }),
+        _ => Ok(provider_chain_id),
+    }
+}
+
+/// Resolve the EIP-712 domain of attestations on the chain
+///
+/// The DisputeManager address is taken from the configuration, then from the known chains,
+/// then from the `graphNetwork` entity of the network subgraph.
+pub async fn resolve_attestation_domain(
+    config: &Ethereum,
+    chain_id: u64,
+    network_subgraph_endpoint: &str,
+    network_subgraph_auth_token: Option<&str>,
+) -> Result<AttestationDomain, ChainError> {
+    let dispute_manager = match (&config.dispute_manager, KnownChain::from_chain_id(chain_id)) {
+        (Some(dispute_manager), _) => parse_address(dispute_manager)?,
+        (None, Some(chain)) => parse_address(chain.dispute_manager)?,
+        (None, None) => {
+            fetch_graph_network_address(
+                network_subgraph_endpoint,
+                network_subgraph_auth_token,
+                "disputeManager",
+            )
+            .await?
+        }
+    };
+
+    info!(
+        chain_id,
+        dispute_manager = ?dispute_manager,
+        "Resolved attestation domain"
+    );
+    Ok(AttestationDomain::new(chain_id, dispute_manager.0))
+}
+
+/// Resolve the Staking contract address, from the configuration, then from the known chains,
+/// then from the `graphNetwork` entity of the network subgraph
+pub async fn resolve_staking_address(
+    config: &Ethereum,
+    chain_id: u64,
+    network_subgraph_endpoint: &str,
+    network_subgraph_auth_token: Option<&str>,
+) -> Result<Address, ChainError> {
+    match (
+        &config.staking_contract,
+        KnownChain::from_chain_id(chain_id),
+    ) {
+        (Some(staking), _) => parse_address(staking),
+        (None, Some(chain)) => parse_address(chain.staking),
+        (None, None) => {
+            fetch_graph_network_address(
+                network_subgraph_endpoint,
+                network_subgraph_auth_token,
+                "staking",
+            )
+            .await
+        }
+    }
+}
+
+fn parse_address(value: &str) -> Result<Address, ChainError> {
+    value
+        .parse::<Address>()
+        .map_err(|_| ChainError::InvalidAddress(value.to_string()))
+}
+
+/// Read a contract address from the `graphNetwork` entity of the network subgraph
+async fn fetch_graph_network_address(
+    network_subgraph_endpoint: &str,
+    network_subgraph_auth_token: Option<&str>,
+    field: &str,
+) -> Result<Address, ChainError> {
+    let query = json!({ "query": format!("{{ graphNetwork(id: \"1\") {{ {} }} }}", field) });
+    let response = query_network_subgraph(
+        network_subgraph_endpoint,
+        network_subgraph_auth_token,
+        query,
+    )
+    .await?;
+
+    let address = response
+        .pointer(&format!("/data/graphNetwork/{}", field))
+        .and_then(|address| address.as_str())
+        .ok_or_else(|| ChainError::NetworkSubgraph(format!("Unexpected response: {}", response)))?;
+    parse_address(address)
+}
+
+/// Operators the indexer authorized, according to the network subgraph
+pub async fn fetch_indexer_operators(
+    network_subgraph_endpoint: &str,
+    network_subgraph_auth_token: Option<&str>,
+    indexer: Address,
+) -> Result<Vec<Address>, ChainError> {
+    let query = json!({
+        "query": "query operators($indexer: String!) { indexer(id: $indexer) { account { operators { id } } } }",
+        "variables": { "indexer": format!("{:?}", indexer) },
+    });
+    let response = query_network_subgraph(
+        network_subgraph_endpoint,
+        network_subgraph_auth_token,
+        query,
+    )
+    .await?;
+
+    let indexer = response
+        .pointer("/data/indexer")
+        .ok_or_else(|| ChainError::NetworkSubgraph(format!("Unexpected response: {}", response)))?;
+    // An indexer unknown to the network has no operators
+    indexer
+        .pointer("/account/operators")
+        .and_then(|operators| operators.as_array())
+        .map(|operators| {
+            operators
+                .iter()
+                .filter_map(|operator| operator.get("id")?.as_str())
+                .map(parse_address)
+                .collect()
+        })
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+/// Send a query to the network subgraph, authenticated with its auth token if configured
+async fn query_network_subgraph(
+    network_subgraph_endpoint: &str,
+    network_subgraph_auth_token: Option<&str>,
+    query: Value,
+) -> Result<Value, ChainError> {
+    let mut request = Client::new()
+        .post(network_subgraph_endpoint)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(query.to_string());
+    if let Some(token) = network_subgraph_auth_token {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| ChainError::NetworkSubgraph(e.to_string()))?
+        .text()
+        .await
+        .map_err(|e| ChainError::NetworkSubgraph(e.to_string()))?;
+    serde_json::from_str(&response).map_err(|e| ChainError::NetworkSubgraph(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use axum::{http::HeaderMap, routing::post, Json, Router};
+    use diesel::{
+        r2d2::{ConnectionManager, Pool},
+        PgConnection,
+    };
+    use ethers::providers::Provider;
+    use ethers_core::types::U256;
+
+    use super::*;
+    use crate::readiness::ProbeTargets;
+
+    const DISPUTE_MANAGER: &str = "0x1111111111111111111111111111111111111111";
+    const STAKING: &str = "0x2222222222222222222222222222222222222222";
+
+    fn readiness() -> Readiness {
+        Readiness::new(ProbeTargets {
+            graph_node_query_endpoints: vec![],
+            graph_node_status_endpoint: String::new(),
+            // Never connected to, the dependencies are not probed
+            postgres: Pool::builder()
+                .min_idle(Some(0))
+                .build_unchecked(ConnectionManager::<PgConnection>::new(
+                    "postgres://localhost",
+                )),
+            network_subgraph_endpoint: String::new(),
+            ethereum: String::new(),
+        })
+    }
+
+    /// Network subgraph answering `graphNetwork` queries for requests with the auth token
+    fn serve_network_subgraph(auth_token: &'static str) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move |headers: HeaderMap| async move {
+                let authorized = headers
+                    .get(header::AUTHORIZATION)
+                    .is_some_and(|value| value == format!("Bearer {}", auth_token).as_str());
+                Json(match authorized {
+                    true => json!({
+                        "data": {
+                            "graphNetwork": { "disputeManager": DISPUTE_MANAGER, "staking": STAKING }
+                        }
+                    }),
+                    false => json!({ "errors": [{ "message": "Unauthorized" }] }),
+                })
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        endpoint
+    }
+
+    #[tokio::test]
+    async fn chain_id_of_the_provider_must_match_the_configuration() {
+        let (provider, mock) = Provider::mocked();
+        let mut config = Ethereum::default();
+
+        mock.push(U256::from(42161)).unwrap();
+        assert_eq!(resolve_chain_id(&config, &provider).await.unwrap(), 42161);
+
+        config.chain_id = Some(1);
+        mock.push(U256::from(42161)).unwrap();
+        let error = resolve_chain_id(&config, &provider).await.unwrap_err();
+        assert!(matches!(
+            error,
+            ChainError::ChainIdMismatch {
+                configured: 1,
+                provider: 42161
+            }
+        ));
+        assert!(!error.is_transient());
+
+        // No response queued, as if the provider was down
+        let error = resolve_chain_id(&config, &provider).await.unwrap_err();
+        assert!(error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn chain_context_resolves_once_the_provider_is_reachable() {
+        let (provider, mock) = Provider::mocked();
+        let endpoint = serve_network_subgraph("secret");
+        let readiness = readiness();
+        let config = Ethereum::default();
+
+        let resolution = tokio::spawn({
+            let readiness = readiness.clone();
+            async move {
+                resolve_chain_context(&config, &provider, &endpoint, Some("secret"), &readiness)
+                    .await
+            }
+        });
+        // The provider has no response for the first attempt
+        tokio::time::sleep(INITIAL_RETRY_DELAY / 2).await;
+        let report = readiness.report().await;
+        assert!(!report.ready);
+        assert!(!report.dependencies[&Dependency::Chain].healthy);
+
+        // Unknown chain, contract addresses come from the network subgraph
+        mock.push(U256::from(1337)).unwrap();
+        let context = resolution.await.unwrap().unwrap();
+        assert_eq!(context.chain_id, 1337);
+        assert_eq!(context.staking_address, STAKING.parse::<Address>().unwrap());
+        assert_eq!(
+            context.attestation_domain.dispute_manager,
+            DISPUTE_MANAGER.parse::<Address>().unwrap().0
+        );
+        assert!(readiness.report().await.dependencies[&Dependency::Chain].healthy);
+    }
+
+    #[tokio::test]
+    async fn graph_network_fallback_sends_the_auth_token() {
+        let endpoint = serve_network_subgraph("secret");
+        let config = Ethereum::default();
+
+        assert_eq!(
+            resolve_staking_address(&config, 1337, &endpoint, Some("secret"))
+                .await
+                .unwrap(),
+            STAKING.parse::<Address>().unwrap()
+        );
+        assert!(matches!(
+            resolve_staking_address(&config, 1337, &endpoint, None).await,
+            Err(ChainError::NetworkSubgraph(_))
+        ));
+    }
+}
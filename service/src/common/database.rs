@@ -14,9 +14,9 @@ pub fn establish_connection() -> PgConnection {
         .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
 
+/// Pool of Postgres connections, opened lazily so that the service can start while the
+/// database is down
 pub(crate) fn create_pg_pool(database_url: &str) -> PgPool {
     let manager = ConnectionManager::<PgConnection>::new(database_url);
-    Pool::builder()
-        .build(manager)
-        .expect("Failed to create pool")
+    Pool::builder().build_unchecked(manager)
 }
@@ -3,254 +3,199 @@ use std::{
     fmt::{self, Display},
 };
 
+use axum::http::StatusCode;
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use tracing::{error, warn};
+
+use crate::metrics::INDEXER_ERROR;
+
 const ERROR_BASE_URL: &str = "https://github.com/graphprotocol/indexer/blob/main/docs/errors.md";
 
-#[derive(Debug, Clone)]
-pub enum IndexerErrorCode {
-    IE001,
-    IE002,
-    IE003,
-    IE004,
-    IE005,
-    IE006,
-    IE007,
-    IE008,
-    IE009,
-    IE010,
-    IE011,
-    IE012,
-    IE013,
-    IE014,
-    IE015,
-    IE016,
-    IE017,
-    IE018,
-    IE019,
-    IE020,
-    IE021,
-    IE022,
-    IE023,
-    IE024,
-    IE025,
-    IE026,
-    IE027,
-    IE028,
-    IE029,
-    IE030,
-    IE031,
-    IE032,
-    IE033,
-    IE034,
-    IE035,
-    IE036,
-    IE037,
-    IE038,
-    IE039,
-    IE040,
-    IE041,
-    IE042,
-    IE043,
-    IE044,
-    IE045,
-    IE046,
-    IE047,
-    IE048,
-    IE049,
-    IE050,
-    IE051,
-    IE052,
-    IE053,
-    IE054,
-    IE055,
-    IE056,
-    IE057,
-    IE058,
-    IE059,
-    IE060,
-    IE061,
-    IE062,
-    IE063,
-    IE064,
-    IE065,
-    IE066,
-    IE067,
-    IE068,
-    IE069,
-    IE070,
-    IE071,
-    IE072,
-    IE073,
-    IE074,
-    IE075,
+/// How bad an error is for the operation of the indexer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Expected from time to time, e.g. invalid user input or a reverted transaction
+    Warning,
+    /// An operation failed and needs attention if it keeps failing
+    Error,
+    /// The indexer cannot work properly until it is fixed
+    Critical,
 }
 
-impl fmt::Display for IndexerErrorCode {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            IndexerErrorCode::IE001 => write!(f, "IE001"),
-            IndexerErrorCode::IE002 => write!(f, "IE002"),
-            IndexerErrorCode::IE003 => write!(f, "IE003"),
-            IndexerErrorCode::IE004 => write!(f, "IE004"),
-            IndexerErrorCode::IE005 => write!(f, "IE005"),
-            IndexerErrorCode::IE006 => write!(f, "IE006"),
-            IndexerErrorCode::IE007 => write!(f, "IE007"),
-            IndexerErrorCode::IE008 => write!(f, "IE008"),
-            IndexerErrorCode::IE009 => write!(f, "IE009"),
-            IndexerErrorCode::IE010 => write!(f, "IE010"),
-            IndexerErrorCode::IE011 => write!(f, "IE011"),
-            IndexerErrorCode::IE012 => write!(f, "IE012"),
-            IndexerErrorCode::IE013 => write!(f, "IE013"),
-            IndexerErrorCode::IE014 => write!(f, "IE014"),
-            IndexerErrorCode::IE015 => write!(f, "IE015"),
-            IndexerErrorCode::IE016 => write!(f, "IE016"),
-            IndexerErrorCode::IE017 => write!(f, "IE017"),
-            IndexerErrorCode::IE018 => write!(f, "IE018"),
-            IndexerErrorCode::IE019 => write!(f, "IE019"),
-            IndexerErrorCode::IE020 => write!(f, "IE020"),
-            IndexerErrorCode::IE021 => write!(f, "IE021"),
-            IndexerErrorCode::IE022 => write!(f, "IE022"),
-            IndexerErrorCode::IE023 => write!(f, "IE023"),
-            IndexerErrorCode::IE024 => write!(f, "IE024"),
-            IndexerErrorCode::IE025 => write!(f, "IE025"),
-            IndexerErrorCode::IE026 => write!(f, "IE026"),
-            IndexerErrorCode::IE027 => write!(f, "IE027"),
-            IndexerErrorCode::IE028 => write!(f, "IE028"),
-            IndexerErrorCode::IE029 => write!(f, "IE029"),
-            IndexerErrorCode::IE030 => write!(f, "IE030"),
-            IndexerErrorCode::IE031 => write!(f, "IE031"),
-            IndexerErrorCode::IE032 => write!(f, "IE032"),
-            IndexerErrorCode::IE033 => write!(f, "IE033"),
-            IndexerErrorCode::IE034 => write!(f, "IE034"),
-            IndexerErrorCode::IE035 => write!(f, "IE035"),
-            IndexerErrorCode::IE036 => write!(f, "IE036"),
-            IndexerErrorCode::IE037 => write!(f, "IE037"),
-            IndexerErrorCode::IE038 => write!(f, "IE038"),
-            IndexerErrorCode::IE039 => write!(f, "IE039"),
-            IndexerErrorCode::IE040 => write!(f, "IE040"),
-            IndexerErrorCode::IE041 => write!(f, "IE041"),
-            IndexerErrorCode::IE042 => write!(f, "IE042"),
-            IndexerErrorCode::IE043 => write!(f, "IE043"),
-            IndexerErrorCode::IE044 => write!(f, "IE044"),
-            IndexerErrorCode::IE045 => write!(f, "IE045"),
-            IndexerErrorCode::IE046 => write!(f, "IE046"),
-            IndexerErrorCode::IE047 => write!(f, "IE047"),
-            IndexerErrorCode::IE048 => write!(f, "IE048"),
-            IndexerErrorCode::IE049 => write!(f, "IE049"),
-            IndexerErrorCode::IE050 => write!(f, "IE050"),
-            IndexerErrorCode::IE051 => write!(f, "IE051"),
-            IndexerErrorCode::IE052 => write!(f, "IE052"),
-            IndexerErrorCode::IE053 => write!(f, "IE053"),
-            IndexerErrorCode::IE054 => write!(f, "IE054"),
-            IndexerErrorCode::IE055 => write!(f, "IE055"),
-            IndexerErrorCode::IE056 => write!(f, "IE056"),
-            IndexerErrorCode::IE057 => write!(f, "IE057"),
-            IndexerErrorCode::IE058 => write!(f, "IE058"),
-            IndexerErrorCode::IE059 => write!(f, "IE059"),
-            IndexerErrorCode::IE060 => write!(f, "IE060"),
-            IndexerErrorCode::IE061 => write!(f, "IE061"),
-            IndexerErrorCode::IE062 => write!(f, "IE062"),
-            IndexerErrorCode::IE063 => write!(f, "IE063"),
-            IndexerErrorCode::IE064 => write!(f, "IE064"),
-            IndexerErrorCode::IE065 => write!(f, "IE065"),
-            IndexerErrorCode::IE066 => write!(f, "IE066"),
-            IndexerErrorCode::IE067 => write!(f, "IE067"),
-            IndexerErrorCode::IE068 => write!(f, "IE068"),
-            IndexerErrorCode::IE069 => write!(f, "IE069"),
-            IndexerErrorCode::IE070 => write!(f, "IE070"),
-            IndexerErrorCode::IE071 => write!(f, "IE071"),
-            IndexerErrorCode::IE072 => write!(f, "IE072"),
-            IndexerErrorCode::IE073 => write!(f, "IE073"),
-            IndexerErrorCode::IE074 => write!(f, "IE074"),
-            IndexerErrorCode::IE075 => write!(f, "IE075"),
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Critical => "critical",
         }
     }
 }
 
-impl IndexerErrorCode {
-    pub fn message(&self) -> &'static str {
-        match self {
-            Self::IE001 => "Failed to run database migrations",
-            Self::IE002 => "Invalid Ethereum URL",
-            Self::IE003 => "Failed to index network subgraph",
-            Self::IE004 => "Failed to synchronize with network",
-            Self::IE005 => "Failed to reconcile indexer and network",
-            Self::IE006 => "Failed to cross-check allocation state with contracts",
-            Self::IE007 => "Failed to check for network pause",
-            Self::IE008 => "Failed to check operator status for indexer",
-            Self::IE009 => "Failed to query subgraph deployments worth indexing",
-            Self::IE010 => "Failed to query indexer allocations",
-            Self::IE011 => "Failed to query claimable indexer allocations",
-            Self::IE012 => "Failed to register indexer",
-            Self::IE013 => "Failed to allocate: insufficient free stake",
-            Self::IE014 => "Failed to allocate: allocation not created on chain",
-            Self::IE015 => "Failed to close allocation",
-            Self::IE016 => "Failed to claim allocation",
-            Self::IE017 => "Failed to ensure default global indexing rule",
-            Self::IE018 => "Failed to query indexing status API",
-            Self::IE019 => "Failed to query proof of indexing",
-            Self::IE020 => "Failed to ensure subgraph deployment is indexing",
-            Self::IE021 => "Failed to migrate cost model",
-            Self::IE022 => "Failed to identify attestation signer for allocation",
-            Self::IE023 => "Failed to handle state channel message",
-            Self::IE024 => "Failed to connect to indexing status API",
-            Self::IE025 => "Failed to query indexer management API",
-            Self::IE026 => "Failed to deploy subgraph deployment",
-            Self::IE027 => "Failed to remove subgraph deployment",
-            Self::IE028 => "Failed to reassign subgraph deployment",
-            Self::IE029 => "Invalid Scalar-Receipt header provided",
-            Self::IE030 => "No Scalar-Receipt header provided",
-            Self::IE031 => "Invalid Scalar-Receipt value provided",
-            Self::IE032 => "Failed to process paid query",
-            Self::IE033 => "Failed to process free query",
-            Self::IE034 => "Not authorized as an operator for the indexer",
-            Self::IE035 => "Unhandled promise rejection",
-            Self::IE036 => "Unhandled exception",
-            Self::IE037 => "Failed to query disputable allocations",
-            Self::IE038 => "Failed to query epochs",
-            Self::IE039 => "Failed to store potential POI disputes",
-            Self::IE040 => "Failed to fetch POI disputes",
-            Self::IE041 => "Failed to query transfers to resolve",
-            Self::IE042 => "Failed to add transfer to the database",
-            Self::IE043 => "Failed to mark transfer as resolved",
-            Self::IE044 => "Failed to collect query fees on chain",
-            Self::IE045 => "Failed to queue transfers for resolving",
-            Self::IE046 => "Failed to resolve transfer",
-            Self::IE047 => "Failed to mark transfer as failed",
-            Self::IE048 => "Failed to withdraw query fees for allocation",
-            Self::IE049 => "Failed to clean up transfers for allocation",
-            Self::IE050 => "Transaction reverted due to gas limit being hit",
-            Self::IE051 => "Transaction reverted for unknown reason",
-            Self::IE052 => "Transaction aborted: maximum configured gas price reached",
-            Self::IE053 => "Failed to queue receipts for collecting",
-            Self::IE054 => "Failed to collect receipts in exchange for query fee voucher",
-            Self::IE055 => "Failed to redeem query fee voucher",
-            Self::IE056 => "Failed to remember allocation for collecting receipts later",
-            Self::IE057 => "Transaction reverted due to failing assertion in contract",
-            Self::IE058 => "Transaction failed because nonce has already been used",
-            Self::IE059 => "Failed to check latest operator ETH balance",
-            Self::IE060 => "Failed to allocate: Already allocating to the subgraph deployment",
-            Self::IE061 => "Failed to allocate: Invalid allocation amount provided",
-            Self::IE062 => "Did not receive tx receipt, not authorized or network paused",
-            Self::IE063 => "No active allocation with provided id found",
-            Self::IE064 => {
-                "Failed to unallocate: Allocation cannot be closed in the same epoch it was created"
+/// Entry of the error catalog, as listed by the `/errors` endpoint
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorCatalogEntry {
+    pub code: IndexerErrorCode,
+    pub message: &'static str,
+    pub severity: Severity,
+    pub http_status: u16,
+    pub user_facing: bool,
+    pub doc_url: String,
+}
+
+/// Generate `IndexerErrorCode` and its catalog lookups from a single table of
+/// `code: severity, HTTP status, user facing, message;` entries
+macro_rules! indexer_error_codes {
+    ($($code:ident: $severity:ident, $status:ident, $user_facing:literal, $message:literal;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub enum IndexerErrorCode {
+            $($code,)*
+        }
+
+        impl IndexerErrorCode {
+            /// Every error code, in order
+            pub const ALL: &'static [IndexerErrorCode] = &[$(IndexerErrorCode::$code,)*];
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$code => stringify!($code),)*
+                }
+            }
+
+            pub fn message(&self) -> &'static str {
+                match self {
+                    $(Self::$code => $message,)*
+                }
+            }
+
+            pub fn severity(&self) -> Severity {
+                match self {
+                    $(Self::$code => Severity::$severity,)*
+                }
+            }
+
+            /// Status of the HTTP response when the error ends a request
+            pub fn http_status(&self) -> StatusCode {
+                match self {
+                    $(Self::$code => StatusCode::$status,)*
+                }
+            }
+
+            /// Whether the error is caused by the client and can be shown to it as is
+            pub fn user_facing(&self) -> bool {
+                match self {
+                    $(Self::$code => $user_facing,)*
+                }
             }
-            Self::IE065 => "Failed to unallocate: Allocation has already been closed",
-            Self::IE066 => "Failed to allocate: allocation ID already exists on chain",
-            Self::IE067 => "Failed to query POI for current epoch start block",
-            Self::IE068 => "User-provided POI did not match reference POI from graph-node",
-            Self::IE069 => "Failed to query Epoch Block Oracle Subgraph",
-            Self::IE070 => "Failed to query latest valid epoch and block hash",
-            Self::IE071 => "Add Epoch subgraph support for non-protocol chains",
-            Self::IE072 => "Failed to execute batch tx (contract: staking)",
-            Self::IE073 => "Failed to query subgraph features from indexing statuses endpoint",
-            Self::IE074 => "Failed to resolve the release version",
-            Self::IE075 => "Failed to parse response body to query string",
         }
+    };
+}
+
+indexer_error_codes! {
+    IE001: Critical, INTERNAL_SERVER_ERROR, false, "Failed to run database migrations";
+    IE002: Critical, INTERNAL_SERVER_ERROR, false, "Invalid Ethereum URL";
+    IE003: Error, BAD_GATEWAY, false, "Failed to index network subgraph";
+    IE004: Error, INTERNAL_SERVER_ERROR, false, "Failed to synchronize with network";
+    IE005: Error, INTERNAL_SERVER_ERROR, false, "Failed to reconcile indexer and network";
+    IE006: Error, INTERNAL_SERVER_ERROR, false, "Failed to cross-check allocation state with contracts";
+    IE007: Error, INTERNAL_SERVER_ERROR, false, "Failed to check for network pause";
+    IE008: Error, INTERNAL_SERVER_ERROR, false, "Failed to check operator status for indexer";
+    IE009: Error, BAD_GATEWAY, false, "Failed to query subgraph deployments worth indexing";
+    IE010: Error, BAD_GATEWAY, false, "Failed to query indexer allocations";
+    IE011: Error, BAD_GATEWAY, false, "Failed to query claimable indexer allocations";
+    IE012: Error, INTERNAL_SERVER_ERROR, false, "Failed to register indexer";
+    IE013: Error, CONFLICT, true, "Failed to allocate: insufficient free stake";
+    IE014: Error, INTERNAL_SERVER_ERROR, false, "Failed to allocate: allocation not created on chain";
+    IE015: Error, INTERNAL_SERVER_ERROR, false, "Failed to close allocation";
+    IE016: Error, INTERNAL_SERVER_ERROR, false, "Failed to claim allocation";
+    IE017: Error, INTERNAL_SERVER_ERROR, false, "Failed to ensure default global indexing rule";
+    IE018: Error, BAD_GATEWAY, false, "Failed to query indexing status API";
+    IE019: Error, BAD_GATEWAY, false, "Failed to query proof of indexing";
+    IE020: Error, INTERNAL_SERVER_ERROR, false, "Failed to ensure subgraph deployment is indexing";
+    IE021: Error, INTERNAL_SERVER_ERROR, false, "Failed to migrate cost model";
+    IE022: Error, INTERNAL_SERVER_ERROR, false, "Failed to identify attestation signer for allocation";
+    IE023: Error, INTERNAL_SERVER_ERROR, false, "Failed to handle state channel message";
+    IE024: Error, BAD_GATEWAY, false, "Failed to connect to indexing status API";
+    IE025: Error, BAD_GATEWAY, false, "Failed to query indexer management API";
+    IE026: Error, INTERNAL_SERVER_ERROR, false, "Failed to deploy subgraph deployment";
+    IE027: Error, INTERNAL_SERVER_ERROR, false, "Failed to remove subgraph deployment";
+    IE028: Error, INTERNAL_SERVER_ERROR, false, "Failed to reassign subgraph deployment";
+    IE029: Warning, BAD_REQUEST, true, "Invalid Scalar-Receipt header provided";
+    IE030: Warning, PAYMENT_REQUIRED, true, "No Scalar-Receipt header provided";
+    IE031: Warning, BAD_REQUEST, true, "Invalid Scalar-Receipt value provided";
+    IE032: Error, INTERNAL_SERVER_ERROR, true, "Failed to process paid query";
+    IE033: Error, INTERNAL_SERVER_ERROR, true, "Failed to process free query";
+    IE034: Critical, FORBIDDEN, false, "Not authorized as an operator for the indexer";
+    IE035: Critical, INTERNAL_SERVER_ERROR, false, "Unhandled promise rejection";
+    IE036: Critical, INTERNAL_SERVER_ERROR, false, "Unhandled exception";
+    IE037: Error, BAD_GATEWAY, false, "Failed to query disputable allocations";
+    IE038: Error, BAD_GATEWAY, false, "Failed to query epochs";
+    IE039: Error, INTERNAL_SERVER_ERROR, false, "Failed to store potential POI disputes";
+    IE040: Error, INTERNAL_SERVER_ERROR, false, "Failed to fetch POI disputes";
+    IE041: Error, INTERNAL_SERVER_ERROR, false, "Failed to query transfers to resolve";
+    IE042: Error, INTERNAL_SERVER_ERROR, false, "Failed to add transfer to the database";
+    IE043: Error, INTERNAL_SERVER_ERROR, false, "Failed to mark transfer as resolved";
+    IE044: Error, INTERNAL_SERVER_ERROR, false, "Failed to collect query fees on chain";
+    IE045: Error, INTERNAL_SERVER_ERROR, false, "Failed to queue transfers for resolving";
+    IE046: Error, INTERNAL_SERVER_ERROR, false, "Failed to resolve transfer";
+    IE047: Error, INTERNAL_SERVER_ERROR, false, "Failed to mark transfer as failed";
+    IE048: Error, INTERNAL_SERVER_ERROR, false, "Failed to withdraw query fees for allocation";
+    IE049: Error, INTERNAL_SERVER_ERROR, false, "Failed to clean up transfers for allocation";
+    IE050: Warning, INTERNAL_SERVER_ERROR, false, "Transaction reverted due to gas limit being hit";
+    IE051: Warning, INTERNAL_SERVER_ERROR, false, "Transaction reverted for unknown reason";
+    IE052: Warning, INTERNAL_SERVER_ERROR, false, "Transaction aborted: maximum configured gas price reached";
+    IE053: Error, INTERNAL_SERVER_ERROR, false, "Failed to queue receipts for collecting";
+    IE054: Error, INTERNAL_SERVER_ERROR, false, "Failed to collect receipts in exchange for query fee voucher";
+    IE055: Error, INTERNAL_SERVER_ERROR, false, "Failed to redeem query fee voucher";
+    IE056: Error, INTERNAL_SERVER_ERROR, false, "Failed to remember allocation for collecting receipts later";
+    IE057: Warning, INTERNAL_SERVER_ERROR, false, "Transaction reverted due to failing assertion in contract";
+    IE058: Warning, INTERNAL_SERVER_ERROR, false, "Transaction failed because nonce has already been used";
+    IE059: Error, INTERNAL_SERVER_ERROR, false, "Failed to check latest operator ETH balance";
+    IE060: Warning, CONFLICT, true, "Failed to allocate: Already allocating to the subgraph deployment";
+    IE061: Warning, BAD_REQUEST, true, "Failed to allocate: Invalid allocation amount provided";
+    IE062: Warning, INTERNAL_SERVER_ERROR, false, "Did not receive tx receipt, not authorized or network paused";
+    IE063: Warning, NOT_FOUND, true, "No active allocation with provided id found";
+    IE064: Warning, CONFLICT, true, "Failed to unallocate: Allocation cannot be closed in the same epoch it was created";
+    IE065: Warning, CONFLICT, true, "Failed to unallocate: Allocation has already been closed";
+    IE066: Warning, CONFLICT, true, "Failed to allocate: allocation ID already exists on chain";
+    IE067: Error, INTERNAL_SERVER_ERROR, false, "Failed to query POI for current epoch start block";
+    IE068: Warning, BAD_REQUEST, true, "User-provided POI did not match reference POI from graph-node";
+    IE069: Error, BAD_GATEWAY, false, "Failed to query Epoch Block Oracle Subgraph";
+    IE070: Error, BAD_GATEWAY, false, "Failed to query latest valid epoch and block hash";
+    IE071: Error, INTERNAL_SERVER_ERROR, false, "Add Epoch subgraph support for non-protocol chains";
+    IE072: Error, INTERNAL_SERVER_ERROR, false, "Failed to execute batch tx (contract: staking)";
+    IE073: Error, BAD_GATEWAY, false, "Failed to query subgraph features from indexing statuses endpoint";
+    IE074: Critical, INTERNAL_SERVER_ERROR, false, "Failed to resolve the release version";
+    IE075: Warning, BAD_REQUEST, true, "Failed to parse response body to query string";
+}
+
+impl fmt::Display for IndexerErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
     }
+}
 
+impl IndexerErrorCode {
+    /// Link to the documentation of the error, anchored at its `## IExxx` heading
     pub fn explanation(&self) -> String {
-        format!("{}#{}", ERROR_BASE_URL, self.message())
+        format!("{}#{}", ERROR_BASE_URL, self.as_str().to_lowercase())
+    }
+
+    pub fn catalog_entry(&self) -> ErrorCatalogEntry {
+        ErrorCatalogEntry {
+            code: *self,
+            message: self.message(),
+            severity: self.severity(),
+            http_status: self.http_status().as_u16(),
+            user_facing: self.user_facing(),
+            doc_url: self.explanation(),
+        }
+    }
+
+    pub fn catalog() -> Vec<ErrorCatalogEntry> {
+        Self::ALL.iter().map(Self::catalog_entry).collect()
     }
 }
 
@@ -297,17 +242,57 @@ pub struct IndexerError {
 }
 
 impl IndexerError {
+    /// Every error is counted in the `indexer_error` metric and logged with its cause chain
+    /// as it is built, so that handlers only need to return it
     pub fn new(code: IndexerErrorCode, cause: Option<IndexerErrorCause>) -> Self {
         let explanation = code.message();
-        Self {
+        let error = Self {
             code,
             explanation: explanation.to_string(),
             cause,
+        };
+        error.observe();
+        error
+    }
+
+    fn observe(&self) {
+        let severity = self.code.severity();
+        INDEXER_ERROR
+            .with_label_values(&[self.code.as_str(), severity.as_str()])
+            .inc();
+
+        let causes = self.cause_chain().join(": ");
+        match severity {
+            Severity::Warning => warn!(
+                code = %self.code,
+                severity = severity.as_str(),
+                cause = %causes,
+                "{}",
+                self.explanation
+            ),
+            Severity::Error | Severity::Critical => error!(
+                code = %self.code,
+                severity = severity.as_str(),
+                cause = %causes,
+                "{}",
+                self.explanation
+            ),
+        }
+    }
+
+    /// Cause of the error followed by each of its sources
+    pub fn cause_chain(&self) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut next = self.cause.as_ref().map(|cause| cause as &dyn Error);
+        while let Some(cause) = next {
+            chain.push(cause.to_string());
+            next = cause.source();
         }
+        chain
     }
 
     pub fn code(&self) -> IndexerErrorCode {
-        self.code.clone()
+        self.code
     }
 
     pub fn explanation(&self) -> &str {
@@ -319,11 +304,23 @@ impl IndexerError {
     }
 }
 
+impl Serialize for IndexerError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("IndexerError", 5)?;
+        error.serialize_field("code", &self.code)?;
+        error.serialize_field("message", &self.explanation)?;
+        error.serialize_field("severity", &self.code.severity())?;
+        error.serialize_field("docUrl", &self.code.explanation())?;
+        error.serialize_field("cause", &self.cause.as_ref().map(|cause| cause.to_string()))?;
+        error.end()
+    }
+}
+
 // pub fn indexer_error(code: IndexerErrorCode, cause: Option<IndexerErrorCause>) -> IndexerError {
 //     IndexerError::new(code, cause)
 // }
 pub fn indexer_error(code: IndexerErrorCode) -> IndexerError {
-    IndexerError::new(code.clone(), Some(code.explanation().into()))
+    IndexerError::new(code, None)
 }
 
 impl std::fmt::Display for IndexerError {
@@ -1,5 +1,8 @@
 pub mod address;
+pub mod attestation;
+pub mod chain;
 pub mod database;
 pub mod indexer_error;
+pub mod signer;
 // pub mod query_fee_models;
 // pub mod schema;
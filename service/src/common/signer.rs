@@ -0,0 +1,336 @@
+use std::{fmt::Debug, fs, sync::Arc};
+
+use async_trait::async_trait;
+use ethers::signers::{
+    coins_bip39::English, LocalWallet, MnemonicBuilder, Signer, Wallet, WalletError,
+};
+use ethers_core::{
+    k256::ecdsa::SigningKey,
+    types::{Address, Signature},
+    utils::hex,
+};
+use reqwest::{header, Client};
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::config::Ethereum;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignerError {
+    #[error("No operator signer configured, set a keystore, a mnemonic or a remote signer")]
+    NotConfigured,
+    #[error("Failed to read {0}: {1}")]
+    ReadFile(String, std::io::Error),
+    #[error("Invalid operator wallet: {0}")]
+    Wallet(#[from] WalletError),
+    #[error("Remote signer request failed: {0}")]
+    Remote(String),
+    #[error("Attestation keys can only be derived from a mnemonic")]
+    NoMnemonic,
+    #[error("Invalid attestation key: {0}")]
+    InvalidKey(#[from] secp256k1::Error),
+    #[error("Signature of the operator signer does not recover its address: {0}")]
+    InvalidSignature(String),
+}
+
+/// Operator wallet used to identify the indexer service and derive attestation keys
+#[async_trait]
+pub trait OperatorSigner: Debug + Send + Sync {
+    /// Address of the operator
+    fn address(&self) -> Address;
+
+    /// Sign a message with the EIP-191 personal message prefix
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError>;
+
+    /// Key signing attestations for a deployment, derived from the operator mnemonic the
+    /// same way as the indexer agent derives allocation IDs
+    fn attestation_key(
+        &self,
+        epoch: u64,
+        deployment_ipfs_hash: &str,
+        index: u32,
+    ) -> Result<secp256k1::SecretKey, SignerError>;
+}
+
+/// Operator key held in memory, loaded from a keystore, a mnemonic or a private key
+pub struct LocalSigner {
+    wallet: LocalWallet,
+    mnemonic: Option<String>,
+}
+
+impl Debug for LocalSigner {
+    // Keep the mnemonic out of logs
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalSigner")
+            .field("address", &self.wallet.address())
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalSigner {
+    /// Build from a private key or a mnemonic
+    pub fn from_secret(value: &str) -> Result<Self, SignerError> {
+        match value.parse::<LocalWallet>() {
+            Ok(wallet) => Ok(LocalSigner {
+                wallet,
+                mnemonic: None,
+            }),
+            Err(_) => Ok(LocalSigner {
+                wallet: MnemonicBuilder::<English>::default()
+                    .phrase(value)
+                    .build()?,
+                mnemonic: Some(value.to_string()),
+            }),
+        }
+    }
+
+    /// Decrypt an encrypted JSON keystore
+    pub fn from_keystore(keystore_file: &str, password_file: &str) -> Result<Self, SignerError> {
+        let password = read_secret_file(password_file)?;
+        let wallet = Wallet::<SigningKey>::decrypt_keystore(keystore_file, password)?;
+        Ok(LocalSigner {
+            wallet,
+            mnemonic: None,
+        })
+    }
+}
+
+#[async_trait]
+impl OperatorSigner for LocalSigner {
+    fn address(&self) -> Address {
+        self.wallet.address()
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        Ok(self.wallet.sign_message(message).await?)
+    }
+
+    fn attestation_key(
+        &self,
+        epoch: u64,
+        deployment_ipfs_hash: &str,
+        index: u32,
+    ) -> Result<secp256k1::SecretKey, SignerError> {
+        let mnemonic = self.mnemonic.as_ref().ok_or(SignerError::NoMnemonic)?;
+        let path = std::iter::once(epoch.to_string())
+            .chain(deployment_ipfs_hash.bytes().map(|byte| byte.to_string()))
+            .chain(std::iter::once(index.to_string()))
+            .collect::<Vec<String>>()
+            .join("/");
+        let wallet = MnemonicBuilder::<English>::default()
+            .phrase(mnemonic.as_str())
+            .derivation_path(&format!("m/{}", path))?
+            .build()?;
+        Ok(secp256k1::SecretKey::from_slice(
+            &wallet.signer().to_bytes(),
+        )?)
+    }
+}
+
+/// Operator key held by an external signer speaking the `eth_accounts`/`eth_sign` JSON-RPC
+/// methods, such as Web3Signer in eth1 mode or a node with an unlocked account. Clef is not
+/// supported, as it only signs data through `account_signData`.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    client: Client,
+    url: String,
+    address: Address,
+}
+
+impl RemoteSigner {
+    /// Connect to the remote signer and use the first account it manages
+    pub async fn connect(url: &str) -> Result<Self, SignerError> {
+        let client = Client::new();
+        let accounts = rpc(&client, url, "eth_accounts", json!([])).await?;
+        let address = accounts
+            .as_array()
+            .and_then(|accounts| accounts.first())
+            .and_then(|account| account.as_str())
+            .ok_or_else(|| SignerError::Remote("No account managed by the signer".to_string()))?
+            .parse::<Address>()
+            .map_err(|e| SignerError::Remote(e.to_string()))?;
+
+        Ok(RemoteSigner {
+            client,
+            url: url.to_string(),
+            address,
+        })
+    }
+}
+
+#[async_trait]
+impl OperatorSigner for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let params = json!([
+            format!("{:?}", self.address),
+            format!("0x{}", hex::encode(message))
+        ]);
+        let signature = rpc(&self.client, &self.url, "eth_sign", params).await?;
+        signature
+            .as_str()
+            .ok_or_else(|| SignerError::Remote(format!("Unexpected signature: {}", signature)))?
+            .parse::<Signature>()
+            .map_err(|e| SignerError::Remote(e.to_string()))
+    }
+
+    fn attestation_key(
+        &self,
+        _epoch: u64,
+        _deployment_ipfs_hash: &str,
+        _index: u32,
+    ) -> Result<secp256k1::SecretKey, SignerError> {
+        Err(SignerError::NoMnemonic)
+    }
+}
+
+async fn rpc(
+    client: &Client,
+    url: &str,
+    method: &str,
+    params: Value,
+) -> Result<Value, SignerError> {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let response = client
+        .post(url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(request.to_string())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| SignerError::Remote(e.to_string()))?
+        .text()
+        .await
+        .map_err(|e| SignerError::Remote(e.to_string()))?;
+    let mut response: Value =
+        serde_json::from_str(&response).map_err(|e| SignerError::Remote(e.to_string()))?;
+
+    match response.get("error") {
+        Some(error) => Err(SignerError::Remote(error.to_string())),
+        None => Ok(response["result"].take()),
+    }
+}
+
+fn read_secret_file(path: &str) -> Result<String, SignerError> {
+    fs::read_to_string(path)
+        .map(|secret| secret.trim().to_string())
+        .map_err(|e| SignerError::ReadFile(path.to_string(), e))
+}
+
+/// Sign a probe message and check that the signature recovers the operator address, so that
+/// a misconfigured signer is reported at startup rather than on the first signature
+async fn check_signer(signer: &dyn OperatorSigner) -> Result<(), SignerError> {
+    let message = format!("indexer-service operator check {:?}", signer.address());
+    let signature = signer.sign_message(message.as_bytes()).await?;
+    match signature.recover(message.as_str()) {
+        Ok(address) if address == signer.address() => Ok(()),
+        Ok(address) => Err(SignerError::InvalidSignature(format!(
+            "recovered {:?}",
+            address
+        ))),
+        Err(e) => Err(SignerError::InvalidSignature(e.to_string())),
+    }
+}
+
+/// Load the operator signer from the configured source, in order of preference: remote
+/// signer, keystore, mnemonic file, then mnemonic from the config file or environment
+pub async fn load_operator_signer(
+    config: &Ethereum,
+) -> Result<Arc<dyn OperatorSigner>, SignerError> {
+    let signer: Arc<dyn OperatorSigner> = if let Some(url) = &config.remote_signer_url {
+        Arc::new(RemoteSigner::connect(url).await?)
+    } else if let Some(keystore_file) = &config.keystore_file {
+        let password_file = config
+            .keystore_password_file
+            .as_ref()
+            .ok_or(SignerError::NotConfigured)?;
+        Arc::new(LocalSigner::from_keystore(keystore_file, password_file)?)
+    } else if let Some(mnemonic_file) = &config.mnemonic_file {
+        Arc::new(LocalSigner::from_secret(&read_secret_file(mnemonic_file)?)?)
+    } else if let Some(mnemonic) = &config.mnemonic {
+        Arc::new(LocalSigner::from_secret(mnemonic)?)
+    } else {
+        return Err(SignerError::NotConfigured);
+    };
+    check_signer(signer.as_ref()).await?;
+
+    info!(address = ?signer.address(), "Resolved operator address");
+    Ok(signer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+
+    use axum::{routing::post, Extension, Json, Router};
+
+    use super::*;
+
+    const MNEMONIC: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    /// JSON-RPC signer answering `eth_accounts` and `eth_sign` with a local wallet
+    async fn rpc_stub(
+        Extension(wallet): Extension<LocalWallet>,
+        Json(request): Json<Value>,
+    ) -> Json<Value> {
+        let result = match request["method"].as_str() {
+            Some("eth_accounts") => json!([format!("{:?}", wallet.address())]),
+            Some("eth_sign") => {
+                let message = request["params"][1]
+                    .as_str()
+                    .unwrap()
+                    .trim_start_matches("0x");
+                let signature = wallet
+                    .sign_message(hex::decode(message).unwrap())
+                    .await
+                    .unwrap();
+                json!(format!("0x{}", signature))
+            }
+            _ => {
+                return Json(
+                    json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -32601, "message": "Method not found" } }),
+                )
+            }
+        };
+        Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+    }
+
+    fn serve_rpc_stub(wallet: LocalWallet) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/", post(rpc_stub))
+            .layer(Extension(wallet));
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        addr
+    }
+
+    #[tokio::test]
+    async fn remote_signer_signs_for_its_account() {
+        let wallet = LocalSigner::from_secret(MNEMONIC).unwrap().wallet;
+        let addr = serve_rpc_stub(wallet.clone());
+
+        let config = Ethereum {
+            remote_signer_url: Some(format!("http://{}", addr)),
+            ..Default::default()
+        };
+        let signer = load_operator_signer(&config).await.unwrap();
+        assert_eq!(signer.address(), wallet.address());
+
+        let signature = signer.sign_message(b"attestation").await.unwrap();
+        assert_eq!(signature.recover("attestation").unwrap(), wallet.address());
+        // Attestation keys are derived from the mnemonic, which remote signers keep
+        assert!(matches!(
+            signer.attestation_key(1, "QmVhiE4nax9i86UBnBmQCYDzvjWuwHShYh7aspGPQhU5Sj", 0),
+            Err(SignerError::NoMnemonic)
+        ));
+    }
+}
@@ -1,8 +1,22 @@
+use std::path::PathBuf;
+
 use clap::{command, Args, Parser, ValueEnum};
+use ethers_core::utils::hex;
+use native::signature_verification::AuthorizedSigner;
 
 use serde::{Deserialize, Serialize};
+use url::Url;
 
-use crate::{query_processor::QueryError, util::init_tracing};
+use crate::{
+    logging::{init_tracing, LogHandle},
+    query_processor::QueryError,
+    query_validator::QueryLimits,
+    server::{
+        auth::{AuthToken, AuthTokens, Scope},
+        layers::RouteTimeouts,
+        listener::{ClientAuth, TlsFiles},
+    },
+};
 
 #[derive(Clone, Debug, Parser, Serialize, Deserialize, Default)]
 #[clap(
@@ -20,6 +34,9 @@ pub struct Cli {
     pub postgres: Postgres,
     #[command(flatten)]
     pub network_subgraph: NetworkSubgraph,
+    #[command(flatten)]
+    #[serde(default)]
+    pub server: ServerSettings,
 
     #[arg(
         short,
@@ -50,11 +67,66 @@ pub struct Ethereum {
     pub ethereum_polling_interval: usize,
     #[clap(
         long,
-        value_name = "mnemonic",
-        env = "MNEMONIC",
-        help = "Mnemonic for the operator wallet"
+        value_name = "chain-id",
+        env = "CHAIN_ID",
+        help = "Chain ID of the protocol network, must match the Ethereum provider (defaults to the provider chain ID)"
+    )]
+    pub chain_id: Option<u64>,
+    #[clap(
+        long,
+        value_name = "dispute-manager",
+        env = "DISPUTE_MANAGER",
+        help = "DisputeManager contract address used in the attestation domain (defaults to the known deployment of the chain, then to the network subgraph)"
+    )]
+    pub dispute_manager: Option<String>,
+    #[clap(
+        long,
+        value_name = "staking-contract",
+        env = "STAKING_CONTRACT",
+        help = "Staking contract address (defaults to the known deployment of the chain, then to the network subgraph)"
+    )]
+    pub staking_contract: Option<String>,
+    /// Mnemonic or private key for the operator wallet, only read from the config file or the
+    /// `MNEMONIC` environment variable to keep it out of the process arguments
+    #[clap(skip = std::env::var("MNEMONIC").ok())]
+    pub mnemonic: Option<String>,
+    #[clap(
+        long,
+        value_name = "mnemonic-file",
+        env = "MNEMONIC_FILE",
+        help = "File containing the mnemonic or private key for the operator wallet"
+    )]
+    pub mnemonic_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "keystore-file",
+        env = "KEYSTORE_FILE",
+        help = "Encrypted JSON keystore of the operator wallet"
+    )]
+    pub keystore_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "keystore-password-file",
+        env = "KEYSTORE_PASSWORD_FILE",
+        help = "File containing the password of the operator keystore"
+    )]
+    pub keystore_password_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "remote-signer-url",
+        env = "REMOTE_SIGNER_URL",
+        help = "JSON-RPC endpoint of a remote signer holding the operator key (eth_accounts/eth_sign)"
+    )]
+    pub remote_signer_url: Option<String>,
+    #[clap(
+        long,
+        value_name = "operator-check-interval",
+        env = "OPERATOR_CHECK_INTERVAL",
+        default_value_t = 300_000,
+        help = "Interval (in ms) for checking that the operator is still authorized for the indexer"
     )]
-    pub mnemonic: String,
+    #[serde(default = "default_operator_check_interval")]
+    pub operator_check_interval: u64,
     #[clap(
         long,
         value_name = "indexer-address",
@@ -85,12 +157,56 @@ pub struct IndexerInfrastructure {
     pub metrics_port: u16,
     #[clap(
         long,
-        value_name = "graph-node-query-endpoint",
-        env = "GRAPH_NODE_QUERY_ENDPOINT",
-        default_value_t = String::from("http://0.0.0.0:8000"),
-        help = "Graph node GraphQL HTTP service endpoint"
+        value_name = "graph-node-query-endpoints",
+        env = "GRAPH_NODE_QUERY_ENDPOINTS",
+        alias = "graph-node-query-endpoint",
+        value_delimiter = ',',
+        default_value = "http://0.0.0.0:8000",
+        help = "Graph node GraphQL HTTP service endpoints, comma separated for multiple query nodes"
+    )]
+    #[serde(
+        alias = "graph_node_query_endpoint",
+        deserialize_with = "one_or_many",
+        default = "default_graph_node_query_endpoints"
+    )]
+    pub graph_node_query_endpoints: Vec<String>,
+    #[clap(
+        long,
+        value_name = "graph-node-selection",
+        env = "GRAPH_NODE_SELECTION",
+        value_enum,
+        default_value_t = BackendSelection::LeastLatency,
+        help = "How queries are spread over the graph node query endpoints"
+    )]
+    #[serde(default)]
+    pub graph_node_selection: BackendSelection,
+    #[clap(
+        long,
+        value_name = "graph-node-max-retries",
+        env = "GRAPH_NODE_MAX_RETRIES",
+        default_value_t = 2,
+        help = "Other graph node query endpoints to try when one is unreachable"
+    )]
+    #[serde(default = "default_graph_node_max_retries")]
+    pub graph_node_max_retries: usize,
+    #[clap(
+        long,
+        value_name = "graph-node-failure-threshold",
+        env = "GRAPH_NODE_FAILURE_THRESHOLD",
+        default_value_t = 3,
+        help = "Consecutive failures before a graph node query endpoint is taken out of rotation"
     )]
-    pub graph_node_query_endpoint: String,
+    #[serde(default = "default_graph_node_failure_threshold")]
+    pub graph_node_failure_threshold: u32,
+    #[clap(
+        long,
+        value_name = "graph-node-circuit-cooldown",
+        env = "GRAPH_NODE_CIRCUIT_COOLDOWN",
+        default_value_t = 30_000,
+        help = "Time (in ms) before a graph node query endpoint out of rotation is tried again"
+    )]
+    #[serde(default = "default_graph_node_circuit_cooldown")]
+    pub graph_node_circuit_cooldown: u64,
     #[clap(
         long,
         value_name = "graph-node-status-endpoint",
@@ -99,14 +215,62 @@ pub struct IndexerInfrastructure {
         help = "Graph node endpoint for the index node server"
     )]
     pub graph_node_status_endpoint: String,
+    #[clap(
+        long,
+        value_name = "graph-node-ws-endpoint",
+        env = "GRAPH_NODE_WS_ENDPOINT",
+        help = "Graph node WebSocket endpoint for subscriptions, e.g. ws://localhost:8001. Subscriptions are disabled if unset"
+    )]
+    pub graph_node_ws_endpoint: Option<String>,
+    #[clap(
+        long,
+        value_name = "max-subscription-connections",
+        env = "MAX_SUBSCRIPTION_CONNECTIONS",
+        default_value_t = 10,
+        help = "Subscription connections allowed at once for each auth token"
+    )]
+    #[serde(default = "default_max_subscription_connections")]
+    pub max_subscription_connections: usize,
     #[clap(
         long,
         value_name = "log-level",
         env = "LOG_LEVEL",
-        value_enum,
-        help = "Log level in RUST_LOG format"
+        help = "Log level in RUST_LOG format, with per-module filters such as `info,service::graph_node=debug`"
     )]
     pub log_level: Option<String>,
+    #[clap(
+        long,
+        value_name = "log-format",
+        env = "LOG_FORMAT",
+        value_enum,
+        default_value_t = LogFormat::Pretty,
+        help = "Format of the logs written to stdout and to the log files"
+    )]
+    #[serde(default)]
+    pub log_format: LogFormat,
+    #[clap(
+        long,
+        value_name = "log-directory",
+        env = "LOG_DIRECTORY",
+        help = "Directory to also write logs to, in a new file every day"
+    )]
+    pub log_directory: Option<String>,
+    #[clap(
+        long,
+        value_name = "log-max-files",
+        env = "LOG_MAX_FILES",
+        default_value_t = 7,
+        help = "Daily log files kept in the log directory, older ones are deleted (0 keeps them all)"
+    )]
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
+    #[clap(
+        long,
+        value_name = "otlp-endpoint",
+        env = "OTEL_EXPORTER_OTLP_ENDPOINT",
+        help = "OpenTelemetry collector to export traces to over OTLP/gRPC, requires the `otlp` feature"
+    )]
+    pub otlp_endpoint: Option<String>,
     #[clap(
         long,
         value_name = "gcloud-profiling",
@@ -122,6 +286,307 @@ pub struct IndexerInfrastructure {
         help = "Auth token that clients can use to query for free"
     )]
     pub free_query_auth_token: Option<String>,
+    #[clap(
+        long,
+        value_name = "auth-tokens-file",
+        env = "AUTH_TOKENS_FILE",
+        help = "File of named bearer tokens, one `name:scope[+scope...]:token` per line with scopes among free-query, network, status and admin. Tokens can also be set comma separated in the AUTH_TOKENS environment variable"
+    )]
+    pub auth_tokens_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "require-status-auth",
+        env = "REQUIRE_STATUS_AUTH",
+        default_value_t = false,
+        help = "Whether /status queries require a token with the status scope"
+    )]
+    #[serde(default)]
+    pub require_status_auth: bool,
+    #[clap(
+        long,
+        value_name = "free-query-limits",
+        env = "FREE_QUERY_LIMITS",
+        default_value = "depth=32,fields=2000,aliases=100,first=1000,skip=5000",
+        help = "Limits on subgraph queries as `depth=..,fields=..,aliases=..,first=..,skip=..`, omitted limits are not enforced"
+    )]
+    #[serde(default)]
+    pub free_query_limits: QueryLimits,
+    #[clap(
+        long,
+        value_name = "indexer-management-url",
+        env = "INDEXER_MANAGEMENT_URL",
+        default_value_t = String::from("http://localhost:18000"),
+        help = "Indexer agent management API, the source of cost models and indexing rules"
+    )]
+    #[serde(default = "default_indexer_management_url")]
+    pub indexer_management_url: String,
+    #[clap(
+        long,
+        value_name = "indexer-management-refresh-interval",
+        env = "INDEXER_MANAGEMENT_REFRESH_INTERVAL",
+        default_value_t = 30_000,
+        help = "Interval (in ms) for refreshing cost models, indexing rules and actions from the indexer management API"
+    )]
+    #[serde(default = "default_indexer_management_refresh_interval")]
+    pub indexer_management_refresh_interval: u64,
+    #[clap(
+        long,
+        value_name = "readiness-check-interval",
+        env = "READINESS_CHECK_INTERVAL",
+        default_value_t = 10_000,
+        help = "Interval (in ms) for probing graph-node, Postgres, the network subgraph and the Ethereum provider"
+    )]
+    #[serde(default = "default_readiness_check_interval")]
+    pub readiness_check_interval: u64,
+    #[clap(
+        long,
+        value_name = "query-cache-size",
+        env = "QUERY_CACHE_SIZE",
+        default_value_t = 0,
+        help = "Maximum number of subgraph query responses to cache, 0 disables the cache"
+    )]
+    #[serde(default)]
+    pub query_cache_size: usize,
+    #[clap(
+        long,
+        value_name = "query-cache-ttl",
+        env = "QUERY_CACHE_TTL",
+        default_value_t = 30_000,
+        help = "Maximum time (in ms) a cached query response is served"
+    )]
+    #[serde(default = "default_query_cache_ttl")]
+    pub query_cache_ttl: u64,
+    #[clap(
+        long,
+        value_name = "query-cache-head-polling-interval",
+        env = "QUERY_CACHE_HEAD_POLLING_INTERVAL",
+        default_value_t = 1_000,
+        help = "Polling interval (in ms) of deployment heads for invalidating cached responses"
+    )]
+    #[serde(default = "default_query_cache_head_polling_interval")]
+    pub query_cache_head_polling_interval: u64,
+}
+
+#[derive(Clone, Debug, Args, Serialize, Deserialize)]
+#[group(multiple = true)]
+#[serde(default)]
+pub struct ServerSettings {
+    #[clap(
+        long,
+        value_name = "bind-address",
+        env = "BIND_ADDRESS",
+        default_value_t = String::from("0.0.0.0"),
+        help = "Address to serve queries at"
+    )]
+    pub bind_address: String,
+    #[clap(
+        long,
+        value_name = "metrics-bind-address",
+        env = "METRICS_BIND_ADDRESS",
+        default_value_t = String::from("0.0.0.0"),
+        help = "Address to serve Prometheus metrics at"
+    )]
+    pub metrics_bind_address: String,
+    #[clap(
+        long,
+        value_name = "tls-cert-file",
+        env = "TLS_CERT_FILE",
+        help = "PEM certificate chain to serve queries over TLS, plain HTTP if unset"
+    )]
+    pub tls_cert_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "tls-key-file",
+        env = "TLS_KEY_FILE",
+        help = "PEM private key of the query server certificate"
+    )]
+    pub tls_key_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "tls-client-ca-file",
+        env = "TLS_CLIENT_CA_FILE",
+        help = "PEM CA certificates of the clients allowed to /status and /network, which are then only served to clients with a certificate signed by them"
+    )]
+    pub tls_client_ca_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "metrics-tls-cert-file",
+        env = "METRICS_TLS_CERT_FILE",
+        help = "PEM certificate chain to serve metrics over TLS, plain HTTP if unset"
+    )]
+    pub metrics_tls_cert_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "metrics-tls-key-file",
+        env = "METRICS_TLS_KEY_FILE",
+        help = "PEM private key of the metrics server certificate"
+    )]
+    pub metrics_tls_key_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "metrics-tls-client-ca-file",
+        env = "METRICS_TLS_CLIENT_CA_FILE",
+        help = "PEM CA certificates of the clients allowed to scrape metrics, which then require a client certificate"
+    )]
+    pub metrics_tls_client_ca_file: Option<String>,
+    #[clap(
+        long,
+        value_name = "tls-reload-interval",
+        env = "TLS_RELOAD_INTERVAL",
+        default_value_t = 60_000,
+        help = "Interval (in ms) for checking the TLS files for renewed certificates"
+    )]
+    pub tls_reload_interval: u64,
+    #[clap(
+        long,
+        value_name = "cors-allowed-origins",
+        env = "CORS_ALLOWED_ORIGINS",
+        value_delimiter = ',',
+        help = "Origins allowed to make cross-origin requests, comma separated, `*` for any"
+    )]
+    pub cors_allowed_origins: Vec<String>,
+    #[clap(
+        long,
+        value_name = "cors-allowed-headers",
+        env = "CORS_ALLOWED_HEADERS",
+        value_delimiter = ',',
+        default_value = "content-type,authorization,scalar-receipt",
+        help = "Headers allowed in cross-origin requests, comma separated, `*` for any"
+    )]
+    pub cors_allowed_headers: Vec<String>,
+    #[clap(
+        long,
+        value_name = "request-timeouts",
+        env = "REQUEST_TIMEOUTS",
+        default_value = "*=10000",
+        help = "Request timeouts (in ms) as `*=..,/path-prefix=..`, matched on the longest prefix with `*` for the other routes"
+    )]
+    pub request_timeouts: RouteTimeouts,
+    #[clap(
+        long,
+        value_name = "max-body-size",
+        env = "MAX_BODY_SIZE",
+        default_value_t = 2 * 1024 * 1024,
+        help = "Maximum size (in bytes) of request bodies"
+    )]
+    pub max_body_size: usize,
+    #[clap(
+        long,
+        value_name = "http1-keepalive",
+        env = "HTTP1_KEEPALIVE",
+        action = clap::ArgAction::Set,
+        default_value_t = true,
+        help = "Whether to keep HTTP/1 connections alive between requests"
+    )]
+    pub http1_keepalive: bool,
+    #[clap(
+        long,
+        value_name = "tcp-keepalive",
+        env = "TCP_KEEPALIVE",
+        help = "Idle time (in ms) before sending TCP keep-alive probes, disabled if unset"
+    )]
+    pub tcp_keepalive: Option<u64>,
+    #[clap(
+        long,
+        value_name = "http2-only",
+        env = "HTTP2_ONLY",
+        default_value_t = false,
+        help = "Whether to only accept HTTP/2 connections"
+    )]
+    pub http2_only: bool,
+    #[clap(
+        long,
+        value_name = "http2-keepalive-interval",
+        env = "HTTP2_KEEPALIVE_INTERVAL",
+        help = "Interval (in ms) of HTTP/2 keep-alive pings, disabled if unset"
+    )]
+    pub http2_keepalive_interval: Option<u64>,
+    #[clap(
+        long,
+        value_name = "http2-max-concurrent-streams",
+        env = "HTTP2_MAX_CONCURRENT_STREAMS",
+        help = "Maximum number of concurrent streams on an HTTP/2 connection"
+    )]
+    pub http2_max_concurrent_streams: Option<u32>,
+}
+
+/// Command line defaults, for config files without a `[server]` section or with only some of
+/// its settings
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            bind_address: String::from("0.0.0.0"),
+            metrics_bind_address: String::from("0.0.0.0"),
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_client_ca_file: None,
+            metrics_tls_cert_file: None,
+            metrics_tls_key_file: None,
+            metrics_tls_client_ca_file: None,
+            tls_reload_interval: 60_000,
+            cors_allowed_origins: vec![],
+            cors_allowed_headers: ["content-type", "authorization", "scalar-receipt"]
+                .map(String::from)
+                .to_vec(),
+            request_timeouts: "*=10000".parse().expect("Valid default request timeouts"),
+            max_body_size: 2 * 1024 * 1024,
+            http1_keepalive: true,
+            tcp_keepalive: None,
+            http2_only: false,
+            http2_keepalive_interval: None,
+            http2_max_concurrent_streams: None,
+        }
+    }
+}
+
+impl ServerSettings {
+    /// TLS files of the query server, where client certificates are checked by route
+    pub fn query_tls(&self) -> Result<Option<TlsFiles>, ConfigError> {
+        tls_files(
+            "query",
+            self.tls_cert_file.as_deref(),
+            self.tls_key_file.as_deref(),
+            self.tls_client_ca_file.as_deref(),
+            ClientAuth::Optional,
+        )
+    }
+
+    /// TLS files of the metrics server, where a client CA makes certificates required
+    pub fn metrics_tls(&self) -> Result<Option<TlsFiles>, ConfigError> {
+        tls_files(
+            "metrics",
+            self.metrics_tls_cert_file.as_deref(),
+            self.metrics_tls_key_file.as_deref(),
+            self.metrics_tls_client_ca_file.as_deref(),
+            ClientAuth::Required,
+        )
+    }
+}
+
+fn tls_files(
+    server: &str,
+    cert: Option<&str>,
+    key: Option<&str>,
+    client_ca: Option<&str>,
+    client_auth: ClientAuth,
+) -> Result<Option<TlsFiles>, ConfigError> {
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok(Some(TlsFiles {
+            cert: PathBuf::from(cert),
+            key: PathBuf::from(key),
+            client_ca: client_ca.map(PathBuf::from),
+            client_auth,
+        })),
+        (None, None) if client_ca.is_none() => Ok(None),
+        (None, None) => Err(ConfigError::ValidateInput(format!(
+            "A client CA for the {} server needs TLS, with a certificate and a private key",
+            server
+        ))),
+        _ => Err(ConfigError::ValidateInput(format!(
+            "TLS for the {} server needs both a certificate and a private key",
+            server
+        ))),
+    }
 }
 
 #[derive(Clone, Debug, Args, Serialize, Deserialize, Default)]
@@ -168,6 +633,29 @@ pub struct Postgres {
     pub postgres_password: String,
 }
 
+impl Postgres {
+    /// Connection URL for the configured Postgres database, with the credentials
+    /// percent-encoded
+    pub fn database_url(&self) -> Result<String, ConfigError> {
+        let invalid = |field: &str| {
+            ConfigError::ValidateInput(format!("Invalid Postgres {} for the connection URL", field))
+        };
+        let mut url = Url::parse("postgres://localhost").map_err(|_| invalid("URL"))?;
+        url.set_host(Some(&self.postgres_host))
+            .map_err(|_| invalid("host"))?;
+        let port = u16::try_from(self.postgres_port).map_err(|_| invalid("port"))?;
+        url.set_port(Some(port)).map_err(|_| invalid("port"))?;
+        url.set_path(&self.postgres_database);
+        url.set_username(&self.postgres_username)
+            .map_err(|_| invalid("username"))?;
+        if !self.postgres_password.is_empty() {
+            url.set_password(Some(&self.postgres_password))
+                .map_err(|_| invalid("password"))?;
+        }
+        Ok(url.to_string())
+    }
+}
+
 #[derive(Clone, Debug, Args, Serialize, Deserialize, Default)]
 #[group(required = true, multiple = true)]
 pub struct NetworkSubgraph {
@@ -175,7 +663,7 @@ pub struct NetworkSubgraph {
         long,
         value_name = "network-subgraph-deployment",
         env = "NETWORK_SUBGRAPH_DEPLOYMENT",
-        help = "Network subgraph deployment"
+        help = "Network subgraph deployment indexed by the local graph-node, preferred over the remote endpoint while healthy"
     )]
     pub network_subgraph_deployment: Option<String>,
     #[clap(
@@ -186,6 +674,24 @@ pub struct NetworkSubgraph {
         help = "Endpoint to query the network subgraph from"
     )]
     pub network_subgraph_endpoint: String,
+    #[clap(
+        long,
+        value_name = "network-subgraph-max-lag",
+        env = "NETWORK_SUBGRAPH_MAX_LAG",
+        default_value_t = 50,
+        help = "Blocks the local network subgraph deployment may lag behind the chain head before falling back to the remote endpoint"
+    )]
+    #[serde(default = "default_network_subgraph_max_lag")]
+    pub network_subgraph_max_lag: u64,
+    #[clap(
+        long,
+        value_name = "network-subgraph-health-interval",
+        env = "NETWORK_SUBGRAPH_HEALTH_INTERVAL",
+        default_value_t = 10_000,
+        help = "Interval (in ms) for checking the health of the local network subgraph deployment"
+    )]
+    #[serde(default = "default_network_subgraph_health_interval")]
+    pub network_subgraph_health_interval: u64,
     #[clap(
         long,
         value_name = "network-subgraph-auth-token",
@@ -201,6 +707,15 @@ pub struct NetworkSubgraph {
         help = "Whether to serve the network subgraph at /network"
     )]
     pub serve_network_subgraph: bool,
+    #[clap(
+        long,
+        value_name = "network-query-limits",
+        env = "NETWORK_QUERY_LIMITS",
+        default_value = "depth=32,fields=2000,aliases=100,first=1000,skip=5000",
+        help = "Limits on /network queries as `depth=..,fields=..,aliases=..,first=..,skip=..`, omitted limits are not enforced"
+    )]
+    #[serde(default)]
+    pub network_query_limits: QueryLimits,
     #[clap(
         long,
         value_name = "allocation-syncing-interval",
@@ -211,18 +726,182 @@ pub struct NetworkSubgraph {
     pub allocation_syncing_interval: u32,
     #[clap(
         long,
-        value_name = "client-signer-address",
-        env = "CLIENT_SIGNER_ADDRESS",
-        help = "Address that signs query fee receipts from a known client"
+        value_name = "client-signer-addresses",
+        env = "CLIENT_SIGNER_ADDRESSES",
+        alias = "client-signer-address",
+        value_delimiter = ',',
+        help = "Addresses that sign query fee receipts from known clients, as comma separated `address[:valid_from[:valid_until]]` with optional unix timestamps bounding when each signer is trusted"
+    )]
+    #[serde(
+        alias = "client_signer_address",
+        deserialize_with = "one_or_many",
+        default
     )]
-    pub client_signer_address: Option<String>,
+    pub client_signer_addresses: Vec<String>,
+}
+
+impl NetworkSubgraph {
+    /// Parse the signers trusted to sign query fee receipts
+    pub fn client_signers(&self) -> Result<Vec<AuthorizedSigner>, ConfigError> {
+        self.client_signer_addresses
+            .iter()
+            .map(|signer| parse_client_signer(signer))
+            .collect()
+    }
+}
+
+/// Parse a client signer as `address[:valid_from[:valid_until]]`
+pub fn parse_client_signer(value: &str) -> Result<AuthorizedSigner, ConfigError> {
+    let invalid = |reason: &str| {
+        ConfigError::ValidateInput(format!("Invalid client signer `{}`: {}", value, reason))
+    };
+    let parse_timestamp = |timestamp: Option<&str>| match timestamp {
+        None | Some("") => Ok(None),
+        Some(timestamp) => timestamp
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| invalid("validity bounds must be unix timestamps")),
+    };
+
+    let mut parts = value.trim().split(':');
+    let address = parts.next().unwrap_or_default();
+    let address: [u8; 20] = hex::decode(address.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("expected a 20 bytes hex address"))?;
+    let valid_from = parse_timestamp(parts.next())?;
+    let valid_until = parse_timestamp(parts.next())?;
+    if parts.next().is_some() {
+        return Err(invalid("expected `address[:valid_from[:valid_until]]`"));
+    }
+
+    Ok(AuthorizedSigner {
+        address,
+        valid_from,
+        valid_until,
+    })
+}
+
+/// Single value from configs written before a setting took a list, such as the graph node
+/// query endpoint or the client signer address, or a list
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(endpoint) => vec![endpoint],
+        OneOrMany::Many(endpoints) => endpoints,
+    })
+}
+
+// Defaults of the settings added since the first config files, matching the command line
+// defaults, so that files written before them keep loading
+fn default_graph_node_query_endpoints() -> Vec<String> {
+    vec![String::from("http://0.0.0.0:8000")]
+}
+
+fn default_graph_node_max_retries() -> usize {
+    2
+}
+
+fn default_graph_node_failure_threshold() -> u32 {
+    3
+}
+
+fn default_graph_node_circuit_cooldown() -> u64 {
+    30_000
+}
+
+fn default_readiness_check_interval() -> u64 {
+    10_000
+}
+
+fn default_query_cache_ttl() -> u64 {
+    30_000
+}
+
+fn default_query_cache_head_polling_interval() -> u64 {
+    1_000
+}
+
+fn default_operator_check_interval() -> u64 {
+    300_000
+}
+
+fn default_network_subgraph_max_lag() -> u64 {
+    50
+}
+
+fn default_network_subgraph_health_interval() -> u64 {
+    10_000
+}
+
+fn default_indexer_management_url() -> String {
+    String::from("http://localhost:18000")
+}
+
+fn default_indexer_management_refresh_interval() -> u64 {
+    30_000
+}
+
+fn default_max_subscription_connections() -> usize {
+    10
+}
+
+fn default_log_max_files() -> usize {
+    7
 }
 
 impl Cli {
+    /// Tokens accepted by the server, including the free query and network subgraph tokens
+    pub fn auth_tokens(&self) -> Result<AuthTokens, ConfigError> {
+        // Tokens are kept out of the command line, where other users of the host can read them
+        let mut tokens = match &self.indexer_infrastructure.auth_tokens_file {
+            Some(path) => AuthTokens::parse_file(&std::fs::read_to_string(path).map_err(|e| {
+                ConfigError::ValidateInput(format!("Read auth tokens file {}: {}", path, e))
+            })?)?,
+            None => vec![],
+        };
+        if let Ok(env_tokens) = std::env::var("AUTH_TOKENS") {
+            tokens.extend(
+                env_tokens
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|token| !token.is_empty())
+                    .map(|token| token.parse::<AuthToken>())
+                    .collect::<Result<Vec<AuthToken>, ConfigError>>()?,
+            );
+        }
+        if let Some(token) = &self.indexer_infrastructure.free_query_auth_token {
+            tokens.push(AuthToken::new("free-query", token, [Scope::FreeQuery]));
+        }
+        if let Some(token) = &self.network_subgraph.network_subgraph_auth_token {
+            tokens.push(AuthToken::new("network-subgraph", token, [Scope::Network]));
+        }
+        Ok(AuthTokens::new(tokens))
+    }
+
     /// Parse config arguments
     /// If environmental variable for config is set to a valid config file path, then parse from config
     /// Otherwise parse from command line arguments
     pub fn args() -> Self {
+        // Environments set up for a single query endpoint or client signer keep working
+        for (variable, single) in [
+            ("GRAPH_NODE_QUERY_ENDPOINTS", "GRAPH_NODE_QUERY_ENDPOINT"),
+            ("CLIENT_SIGNER_ADDRESSES", "CLIENT_SIGNER_ADDRESS"),
+        ] {
+            if std::env::var_os(variable).is_none() {
+                if let Some(value) = std::env::var_os(single) {
+                    std::env::set_var(variable, value);
+                }
+            }
+        }
         let cli = if let Ok(file_path) = std::env::var("config") {
             confy::load_path::<Cli>(file_path.clone())
                 .unwrap_or_else(|_| panic!("Parse config file at {}", file_path.clone()))
@@ -232,14 +911,23 @@ impl Cli {
             // let _ = confy::store_path("./args.toml", cli.clone());
         };
 
-        // Enables tracing under RUST_LOG variable
-        if let Some(log_setting) = &cli.indexer_infrastructure.log_level {
-            std::env::set_var("RUST_LOG", log_setting);
-        };
-        // add a LogFormat to config
-        init_tracing("pretty".to_string()).expect("Could not set up global default subscriber for logger, check environmental variable `RUST_LOG` or the CLI input `log-level`");
         cli
     }
+
+    /// Set up tracing from the log level, format and directory
+    pub fn init_logging(&self) -> LogHandle {
+        init_tracing(
+            self.indexer_infrastructure.log_format,
+            self.indexer_infrastructure.log_level.as_deref(),
+            self.indexer_infrastructure
+                .log_directory
+                .as_ref()
+                .map(PathBuf::from),
+            self.indexer_infrastructure.log_max_files,
+            self.indexer_infrastructure.otlp_endpoint.as_deref(),
+        )
+        .expect("Could not set up global default subscriber for logger, check environmental variable `RUST_LOG` or the CLI input `log-level`")
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -256,6 +944,18 @@ pub enum ConfigError {
     Other(anyhow::Error),
 }
 
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize, Default,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendSelection {
+    /// Send queries to the endpoint with the lowest average latency
+    #[default]
+    LeastLatency,
+    /// Rotate queries over the endpoints
+    RoundRobin,
+}
+
 #[derive(
     Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize, Default,
 )]
@@ -266,5 +966,159 @@ pub enum LogLevel {
     Info,
     Warn,
     Error,
+    /// Same as `Error`, tracing has no level above it
     Fatal,
 }
+
+impl LogLevel {
+    pub fn as_directive(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error | LogLevel::Fatal => "error",
+        }
+    }
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize, Default,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    /// Multi-line, human readable logs
+    #[default]
+    Pretty,
+    /// Single-line logs with every field
+    Full,
+    /// Shorter single-line logs
+    Compact,
+    /// Newline-delimited JSON
+    Json,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Config file written before the server section and the settings added since
+    const BASELINE_TEMPLATE: &str = r#"
+[ethereum]
+ethereum = 'https://rinkeby.infura.io/v3/db591449ac53444cae5873f5bdf4d5fa'
+ethereum_polling_interval = 4000
+mnemonic = 'abondon abondon abondon abondon abondon abondon abondon abondon abondon abondon abondon abondon abondon abondon abondon'
+indexer_address = '0xAcb05407d78129b5717bB51712D3e23a78A10929'
+
+[indexer_infrastructure]
+port = 7300
+metrics_port = 7500
+graph_node_query_endpoint = 'http://localhost:8000'
+graph_node_status_endpoint = 'http://localhost:8030/graphql'
+log_level = 'Debug'
+gcloud_profiling = false
+free_query_auth_token = 'free-query-auth-token'
+
+[postgres]
+postgres_host = '127.0.0.1'
+postgres_port = 5432
+postgres_database = 'graph-node'
+postgres_username = 'user'
+postgres_password = 'pswd'
+
+[network_subgraph]
+network_subgraph_endpoint = 'https://api.thegraph.com/subgraphs/name/graphprotocol/graph-network-testnet'
+network_subgraph_auth_token = 'network-subgraph-auth-token'
+serve_network_subgraph = true
+allocation_syncing_interval = 120000
+client_signer_address = '0xe1EC4339019eC9628438F8755f847e3023e4ff9c'
+"#;
+
+    fn load(name: &str, contents: &str) -> Cli {
+        let path = std::env::temp_dir().join(format!("{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let cli = confy::load_path::<Cli>(&path);
+        std::fs::remove_file(&path).unwrap();
+        cli.unwrap()
+    }
+
+    #[test]
+    fn config_files_without_the_new_settings_load_with_the_command_line_defaults() {
+        let cli = load("baseline-template", BASELINE_TEMPLATE);
+        let defaults = Cli::try_parse_from([
+            "indexer-service",
+            "--ethereum",
+            "http://localhost:8545",
+            "--indexer-address",
+            "0xAcb05407d78129b5717bB51712D3e23a78A10929",
+            "--port",
+            "7300",
+            "--postgres-database",
+            "graph-node",
+            "--allocation-syncing-interval",
+            "120000",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            format!("{:?}", cli.server),
+            format!("{:?}", defaults.server)
+        );
+        assert_eq!(
+            cli.indexer_infrastructure.graph_node_query_endpoints,
+            vec!["http://localhost:8000".to_string()]
+        );
+        assert_eq!(
+            cli.network_subgraph.client_signers().unwrap()[0].address,
+            <[u8; 20]>::try_from(hex::decode("e1EC4339019eC9628438F8755f847e3023e4ff9c").unwrap())
+                .unwrap()
+        );
+        let (loaded, default) = (
+            &cli.indexer_infrastructure,
+            &defaults.indexer_infrastructure,
+        );
+        assert_eq!(
+            loaded.max_subscription_connections,
+            default.max_subscription_connections
+        );
+        assert_eq!(loaded.log_format, default.log_format);
+        assert_eq!(loaded.log_max_files, default.log_max_files);
+        assert_eq!(
+            loaded.readiness_check_interval,
+            default.readiness_check_interval
+        );
+        assert_eq!(loaded.query_cache_size, default.query_cache_size);
+        assert_eq!(loaded.query_cache_ttl, default.query_cache_ttl);
+        assert_eq!(
+            loaded.query_cache_head_polling_interval,
+            default.query_cache_head_polling_interval
+        );
+        assert_eq!(
+            loaded.indexer_management_url,
+            default.indexer_management_url
+        );
+        assert_eq!(
+            loaded.indexer_management_refresh_interval,
+            default.indexer_management_refresh_interval
+        );
+        assert_eq!(
+            cli.ethereum.operator_check_interval,
+            defaults.ethereum.operator_check_interval
+        );
+        assert_eq!(
+            cli.network_subgraph.network_subgraph_max_lag,
+            defaults.network_subgraph.network_subgraph_max_lag
+        );
+        assert_eq!(
+            cli.network_subgraph.network_subgraph_health_interval,
+            defaults.network_subgraph.network_subgraph_health_interval
+        );
+    }
+
+    #[test]
+    fn template_config_file_loads() {
+        let cli = load("template", include_str!("../../template.toml"));
+        assert_eq!(cli.server.max_body_size, 2 * 1024 * 1024);
+        assert_eq!(cli.indexer_infrastructure.query_cache_size, 10_000);
+    }
+}
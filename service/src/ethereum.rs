@@ -0,0 +1,236 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use ethers::{
+    contract::abigen,
+    providers::Middleware,
+    types::{Address, U256},
+    utils::format_ether,
+};
+use tracing::debug;
+
+use crate::{
+    common::{
+        chain::fetch_indexer_operators,
+        indexer_error::{IndexerError, IndexerErrorCause, IndexerErrorCode},
+    },
+    metrics::{ETHEREUM_BLOCK_NUMBER, OPERATOR_ETH_BALANCE},
+};
+
+abigen!(
+    Staking,
+    r#"[
+        function isOperator(address _operator, address _indexer) external view returns (bool)
+        function getAllocationState(address _allocationID) external view returns (uint8)
+    ]"#
+);
+
+/// State of an allocation in the Staking contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationState {
+    Null,
+    Active,
+    Closed,
+    Finalized,
+    Claimed,
+}
+
+impl TryFrom<u8> for AllocationState {
+    type Error = IndexerError;
+
+    fn try_from(state: u8) -> Result<Self, Self::Error> {
+        match state {
+            0 => Ok(AllocationState::Null),
+            1 => Ok(AllocationState::Active),
+            2 => Ok(AllocationState::Closed),
+            3 => Ok(AllocationState::Finalized),
+            4 => Ok(AllocationState::Claimed),
+            state => Err(IndexerError::new(
+                IndexerErrorCode::IE006,
+                Some(format!("Unknown allocation state {}", state).into()),
+            )),
+        }
+    }
+}
+
+/// Latest values polled from the Ethereum provider
+#[derive(Debug, Default, Clone)]
+pub struct ChainState {
+    pub block_number: Option<u64>,
+    pub operator_balance: Option<U256>,
+    /// Whether the operator may act for the indexer, as of the last authorization check
+    pub operator_authorized: Option<bool>,
+}
+
+/// Ethereum provider of the protocol network, polled at the configured interval for the
+/// latest block and the operator balance, and used to read the Staking contract
+#[derive(Debug)]
+pub struct EthereumClient<M> {
+    provider: Arc<M>,
+    staking: Staking<M>,
+    chain_id: u64,
+    operator: Address,
+    indexer: Address,
+    network_subgraph_endpoint: String,
+    network_subgraph_auth_token: Option<String>,
+    state: RwLock<ChainState>,
+}
+
+impl<M: Middleware + 'static> EthereumClient<M> {
+    pub fn new(
+        provider: M,
+        chain_id: u64,
+        staking_address: Address,
+        operator: Address,
+        indexer: Address,
+        network_subgraph_endpoint: String,
+        network_subgraph_auth_token: Option<String>,
+    ) -> Self {
+        let provider = Arc::new(provider);
+        EthereumClient {
+            staking: Staking::new(staking_address, provider.clone()),
+            provider,
+            chain_id,
+            operator,
+            indexer,
+            network_subgraph_endpoint,
+            network_subgraph_auth_token,
+            state: RwLock::new(ChainState::default()),
+        }
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub fn operator(&self) -> Address {
+        self.operator
+    }
+
+    pub fn indexer(&self) -> Address {
+        self.indexer
+    }
+
+    /// Latest polled block number and operator balance
+    pub fn state(&self) -> ChainState {
+        self.state.read().unwrap().clone()
+    }
+
+    pub async fn block_number(&self) -> Result<u64, IndexerError> {
+        let block_number = self
+            .provider
+            .get_block_number()
+            .await
+            .map_err(|e| IndexerError::new(IndexerErrorCode::IE002, Some(cause(e))))?
+            .as_u64();
+        self.state.write().unwrap().block_number = Some(block_number);
+        ETHEREUM_BLOCK_NUMBER.set(block_number as i64);
+        Ok(block_number)
+    }
+
+    pub async fn operator_balance(&self) -> Result<U256, IndexerError> {
+        let balance = self
+            .provider
+            .get_balance(self.operator, None)
+            .await
+            .map_err(|e| IndexerError::new(IndexerErrorCode::IE059, Some(cause(e))))?;
+        self.state.write().unwrap().operator_balance = Some(balance);
+        OPERATOR_ETH_BALANCE.set(format_ether(balance).parse().unwrap_or_default());
+        Ok(balance)
+    }
+
+    /// Whether the operator is authorized to act for the indexer in the Staking contract
+    pub async fn is_operator(&self) -> Result<bool, IndexerError> {
+        self.staking
+            .is_operator(self.operator, self.indexer)
+            .call()
+            .await
+            .map_err(|e| IndexerError::new(IndexerErrorCode::IE008, Some(cause(e))))
+    }
+
+    /// Check that the operator may act for the indexer, through the Staking contract or
+    /// the network subgraph if the contract cannot be read. Fails with `IE034` if it may not.
+    pub async fn check_operator(&self) -> Result<(), IndexerError> {
+        let authorized = if self.indexer == self.operator {
+            true
+        } else {
+            match self.is_operator().await {
+                Ok(authorized) => authorized,
+                Err(_) => {
+                    debug!("Falling back to the network subgraph for the operator status");
+                    fetch_indexer_operators(
+                        &self.network_subgraph_endpoint,
+                        self.network_subgraph_auth_token.as_deref(),
+                        self.indexer,
+                    )
+                    .await
+                    .map_err(|e| IndexerError::new(IndexerErrorCode::IE008, Some(cause(e))))?
+                    .contains(&self.operator)
+                }
+            }
+        };
+        self.state.write().unwrap().operator_authorized = Some(authorized);
+
+        if authorized {
+            Ok(())
+        } else {
+            Err(IndexerError::new(
+                IndexerErrorCode::IE034,
+                Some(
+                    format!(
+                        "Operator {:?} is not authorized for indexer {:?}",
+                        self.operator, self.indexer
+                    )
+                    .into(),
+                ),
+            ))
+        }
+    }
+
+    pub async fn allocation_state(
+        &self,
+        allocation: Address,
+    ) -> Result<AllocationState, IndexerError> {
+        let state = self
+            .staking
+            .get_allocation_state(allocation)
+            .call()
+            .await
+            .map_err(|e| IndexerError::new(IndexerErrorCode::IE006, Some(cause(e))))?;
+        AllocationState::try_from(state)
+    }
+
+    /// Keep the latest block and the operator balance up to date
+    pub async fn monitor(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            // Failures are logged as the indexer errors are built
+            if let Ok(block_number) = self.block_number().await {
+                debug!(block_number, "Polled latest Ethereum block");
+            }
+            let _ = self.operator_balance().await;
+        }
+    }
+
+    /// Periodically check that the operator is still authorized for the indexer
+    pub async fn monitor_operator(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        // The first tick completes immediately, the startup check already covered it
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+
+            // Failures, unauthorized operators included, are logged as the indexer error
+            // is built
+            let _ = self.check_operator().await;
+        }
+    }
+}
+
+fn cause(error: impl std::error::Error) -> IndexerErrorCause {
+    error.to_string().into()
+}
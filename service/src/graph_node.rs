@@ -1,43 +1,255 @@
-use reqwest::{header, Client, Url};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
-use crate::query_processor::UnattestedQueryResult;
+use reqwest::{header, Client, StatusCode, Url};
+use tracing::{instrument, warn};
 
+use crate::{
+    config::BackendSelection,
+    metrics::{GRAPH_NODE_BACKEND_HEALTHY, GRAPH_NODE_REQUESTS, GRAPH_NODE_REQUEST_DURATION},
+    query_processor::UnattestedQueryResult,
+    telemetry::trace_context_headers,
+};
+
+/// Weight of the latest sample in the latency moving average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Options for spreading queries over the graph-node query endpoints
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub selection: BackendSelection,
+    /// Additional backends to try when a request fails in a way that is safe to retry
+    pub max_retries: usize,
+    /// Consecutive failures after which a backend is taken out of rotation
+    pub failure_threshold: u32,
+    /// How long a backend stays out of rotation before it is tried again
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Default)]
+struct BackendState {
+    consecutive_failures: u32,
+    /// Exponentially weighted moving average of successful request latencies
+    latency_ms: Option<f64>,
+    /// Circuit is open until then, the backend only gets traffic if no other is available
+    open_until: Option<Instant>,
+}
+
+/// A single graph-node query endpoint with its health tracking
+#[derive(Debug)]
+struct Backend {
+    base_url: String,
+    state: Mutex<BackendState>,
+}
+
+impl Backend {
+    fn is_available(&self, now: Instant) -> bool {
+        match self.state.lock().unwrap().open_until {
+            Some(open_until) => open_until <= now,
+            None => true,
+        }
+    }
+
+    fn latency_ms(&self) -> f64 {
+        // Backends that never answered successfully go last, they get a latency sample
+        // once the faster ones fail over to them
+        self.state
+            .lock()
+            .unwrap()
+            .latency_ms
+            .unwrap_or(f64::INFINITY)
+    }
+
+    fn record_success(&self, latency: Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let mut state = self.state.lock().unwrap();
+        state.latency_ms = Some(match state.latency_ms {
+            Some(average) => average + LATENCY_SMOOTHING * (latency_ms - average),
+            None => latency_ms,
+        });
+        state.consecutive_failures = 0;
+        state.open_until = None;
+
+        GRAPH_NODE_REQUESTS
+            .with_label_values(&[self.base_url.as_str(), "success"])
+            .inc();
+        GRAPH_NODE_REQUEST_DURATION
+            .with_label_values(&[self.base_url.as_str()])
+            .observe(latency.as_secs_f64());
+        GRAPH_NODE_BACKEND_HEALTHY
+            .with_label_values(&[self.base_url.as_str()])
+            .set(1);
+    }
+
+    fn record_failure(&self, options: &PoolOptions) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= options.failure_threshold {
+            if state.open_until.is_none() {
+                warn!(
+                    backend = %self.base_url,
+                    failures = state.consecutive_failures,
+                    "Taking graph-node backend out of rotation"
+                );
+            }
+            state.open_until = Some(Instant::now() + options.cooldown);
+            GRAPH_NODE_BACKEND_HEALTHY
+                .with_label_values(&[self.base_url.as_str()])
+                .set(0);
+        }
+
+        GRAPH_NODE_REQUESTS
+            .with_label_values(&[self.base_url.as_str(), "failure"])
+            .inc();
+    }
+}
+
+/// Pool of graph-node query endpoints with load balancing, retries and circuit breaking
 #[derive(Debug, Clone)]
 pub struct GraphNodeInstance {
     client: Client,
-    base_url: String,
+    backends: Arc<Vec<Backend>>,
+    next: Arc<AtomicUsize>,
+    options: PoolOptions,
 }
 
 impl GraphNodeInstance {
-    pub fn new(base_url: &str) -> GraphNodeInstance {
+    pub fn new(base_urls: &[String], options: PoolOptions) -> GraphNodeInstance {
+        assert!(
+            !base_urls.is_empty(),
+            "At least one graph node query endpoint is required"
+        );
         let client = reqwest::Client::builder()
             .user_agent("indexer-service")
             .build()
             .expect("Could not build a client to graph node query endpoint");
+        let backends = base_urls
+            .iter()
+            .map(|base_url| {
+                let base_url = base_url.trim_end_matches('/').to_string();
+                GRAPH_NODE_BACKEND_HEALTHY
+                    .with_label_values(&[base_url.as_str()])
+                    .set(1);
+                Backend {
+                    base_url,
+                    state: Mutex::new(BackendState::default()),
+                }
+            })
+            .collect();
+
         GraphNodeInstance {
             client,
-            base_url: base_url.to_string(),
+            backends: Arc::new(backends),
+            next: Arc::new(AtomicUsize::new(0)),
+            options,
         }
     }
 
+    /// Order in which backends are tried for the next request
+    fn candidates(&self) -> Vec<&Backend> {
+        let now = Instant::now();
+        let mut available: Vec<&Backend> = self
+            .backends
+            .iter()
+            .filter(|backend| backend.is_available(now))
+            .collect();
+
+        if available.is_empty() {
+            // Every circuit is open, fall back to the backend that is closest to recovering
+            let mut backends: Vec<&Backend> = self.backends.iter().collect();
+            backends.sort_by_key(|backend| backend.state.lock().unwrap().open_until);
+            return backends;
+        }
+
+        match self.options.selection {
+            BackendSelection::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed) % available.len();
+                available.rotate_left(start);
+            }
+            BackendSelection::LeastLatency => {
+                available.sort_by(|a, b| a.latency_ms().total_cmp(&b.latency_ms()));
+            }
+        }
+        available
+    }
+
+    /// Post a query to the pool, retrying on other backends if the failure is safe to retry
+    #[instrument(name = "graph_node_query", skip(self, data))]
+    async fn post(&self, path: &str, data: String) -> Result<String, reqwest::Error> {
+        let candidates = self.candidates();
+        let attempts = candidates.len().min(self.options.max_retries + 1);
+
+        for (attempt, backend) in candidates.into_iter().take(attempts).enumerate() {
+            let last_attempt = attempt + 1 == attempts;
+            let start = Instant::now();
+            let result = self
+                .client
+                .post(format!("{}{}", backend.base_url, path))
+                .body(data.clone())
+                .header(header::CONTENT_TYPE, "application/json")
+                .headers(trace_context_headers())
+                .send()
+                .await;
+
+            match result {
+                Ok(response) if response.status().is_server_error() => {
+                    let status = response.status();
+                    if !is_retryable_status(status) {
+                        return Err(response.error_for_status().unwrap_err());
+                    }
+                    backend.record_failure(&self.options);
+                    // A failed response is never handed over as a result to attest
+                    if last_attempt {
+                        return Err(response.error_for_status().unwrap_err());
+                    }
+                    warn!(
+                        backend = %backend.base_url,
+                        %status,
+                        "Graph node backend unavailable, retrying on another backend"
+                    );
+                }
+                Ok(response) => {
+                    backend.record_success(start.elapsed());
+                    return response.text().await;
+                }
+                Err(e) if !last_attempt && (e.is_connect() || e.is_timeout()) => {
+                    backend.record_failure(&self.options);
+                    warn!(
+                        backend = %backend.base_url,
+                        error = %e,
+                        "Graph node backend unreachable, retrying on another backend"
+                    );
+                }
+                Err(e) => {
+                    backend.record_failure(&self.options);
+                    return Err(e);
+                }
+            }
+        }
+
+        unreachable!("The graph node pool always has at least one backend")
+    }
+
     pub async fn subgraph_query(
         &self,
         endpoint: &str,
         data: String,
     ) -> Result<UnattestedQueryResult, reqwest::Error> {
-        let request = self
-            .client
-            .post(format!("{}/subgraphs/id/{}", self.base_url, endpoint))
-            .body(data.clone())
-            .header(header::CONTENT_TYPE, "application/json");
-
-        let response = request.send().await?.text().await?;
+        let response = self
+            .post(&format!("/subgraphs/id/{}", endpoint), data)
+            .await?;
         Ok(UnattestedQueryResult {
             graphql_response: response,
             attestable: true,
         })
     }
 
+    #[instrument(name = "network_subgraph_query", skip(self, data))]
     pub async fn network_query(
         &self,
         endpoint: Url,
@@ -47,7 +259,8 @@ impl GraphNodeInstance {
             .client
             .post(endpoint)
             .body(data.clone())
-            .header(header::CONTENT_TYPE, "application/json");
+            .header(header::CONTENT_TYPE, "application/json")
+            .headers(trace_context_headers());
 
         let response = request.send().await?;
 
@@ -59,3 +272,11 @@ impl GraphNodeInstance {
         })
     }
 }
+
+/// Gateway errors mean the query never reached a healthy graph-node, so it can be sent elsewhere
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
@@ -0,0 +1,316 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_graphql::SimpleObject;
+use reqwest::{header, Client};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+/// Identifier of the cost model and indexing rule applying to deployments without their own
+pub const GLOBAL_IDENTIFIER: &str = "global";
+
+/// Agora cost model of a deployment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct CostModel {
+    pub deployment: String,
+    pub model: Option<String>,
+    pub variables: Option<String>,
+}
+
+/// Indexing rule set through the indexer agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexingRule {
+    pub identifier: String,
+    pub identifier_type: String,
+    pub decision_basis: String,
+    pub allocation_amount: Option<String>,
+    pub allocation_lifetime: Option<u64>,
+    pub auto_renewal: Option<bool>,
+    pub require_supported: Option<bool>,
+    pub safety: Option<bool>,
+}
+
+/// Action queued for the indexer agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Action {
+    pub id: u64,
+    pub status: String,
+    #[serde(rename = "type")]
+    pub action_type: String,
+    #[serde(rename = "deploymentID")]
+    pub deployment_id: Option<String>,
+    #[serde(rename = "allocationID")]
+    pub allocation_id: Option<String>,
+    pub amount: Option<String>,
+    pub source: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+struct ManagementState {
+    cost_models: HashMap<String, CostModel>,
+    indexing_rules: Vec<IndexingRule>,
+    actions: Vec<Action>,
+}
+
+/// Client of the indexer agent management API, caching cost models, indexing rules and
+/// actions so that the service follows the same configuration as the agent
+#[derive(Debug, Clone)]
+pub struct IndexerManagementClient {
+    client: Client,
+    url: String,
+    state: Arc<RwLock<ManagementState>>,
+}
+
+impl IndexerManagementClient {
+    pub fn new(url: &str) -> Self {
+        IndexerManagementClient {
+            client: Client::new(),
+            url: url.to_string(),
+            state: Arc::new(RwLock::new(ManagementState::default())),
+        }
+    }
+
+    /// Cost model of a deployment, falling back to the global cost model
+    pub fn cost_model(&self, deployment: &str) -> Option<CostModel> {
+        let state = self.state.read().unwrap();
+        state
+            .cost_models
+            .get(deployment)
+            .or_else(|| state.cost_models.get(GLOBAL_IDENTIFIER))
+            .map(|model| CostModel {
+                deployment: deployment.to_string(),
+                ..model.clone()
+            })
+    }
+
+    /// Cost models of the given deployments, or every cost model if none are given
+    pub fn cost_models(&self, deployments: Option<&[String]>) -> Vec<CostModel> {
+        match deployments {
+            Some(deployments) => deployments
+                .iter()
+                .filter_map(|deployment| self.cost_model(deployment))
+                .collect(),
+            None => self
+                .state
+                .read()
+                .unwrap()
+                .cost_models
+                .values()
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn indexing_rules(&self) -> Vec<IndexingRule> {
+        self.state.read().unwrap().indexing_rules.clone()
+    }
+
+    /// Indexing rule applying to a deployment, falling back to the global rule
+    pub fn indexing_rule(&self, deployment: &str) -> Option<IndexingRule> {
+        let state = self.state.read().unwrap();
+        state
+            .indexing_rules
+            .iter()
+            .find(|rule| rule.identifier == deployment)
+            .or_else(|| {
+                state
+                    .indexing_rules
+                    .iter()
+                    .find(|rule| rule.identifier == GLOBAL_IDENTIFIER)
+            })
+            .cloned()
+    }
+
+    pub fn actions(&self) -> Vec<Action> {
+        self.state.read().unwrap().actions.clone()
+    }
+
+    /// Fetch cost models, indexing rules and actions from the management API. Each is
+    /// refreshed on its own, so that one failing keeps the others up to date.
+    pub async fn refresh(&self) -> Result<(), anyhow::Error> {
+        let (cost_models, indexing_rules, actions) = tokio::join!(
+            self.query::<Vec<CostModel>>(
+                "{ costModels { deployment model variables } }",
+                "costModels",
+            ),
+            self.query::<Vec<IndexingRule>>(
+                "{ indexingRules(merged: false) { identifier identifierType decisionBasis allocationAmount allocationLifetime autoRenewal requireSupported safety } }",
+                "indexingRules",
+            ),
+            self.query::<Vec<Action>>(
+                "{ actions(filter: {}) { id status type deploymentID allocationID amount source reason } }",
+                "actions",
+            ),
+        );
+
+        let mut errors = vec![];
+        let mut state = self.state.write().unwrap();
+        match cost_models {
+            Ok(cost_models) => {
+                debug!(cost_models = cost_models.len(), "Refreshed cost models");
+                state.cost_models = cost_models
+                    .into_iter()
+                    .map(|model| (model.deployment.clone(), model))
+                    .collect();
+            }
+            Err(e) => errors.push(e),
+        }
+        match indexing_rules {
+            Ok(indexing_rules) => {
+                debug!(
+                    indexing_rules = indexing_rules.len(),
+                    "Refreshed indexing rules"
+                );
+                state.indexing_rules = indexing_rules;
+            }
+            Err(e) => errors.push(e),
+        }
+        match actions {
+            Ok(actions) => {
+                debug!(actions = actions.len(), "Refreshed actions");
+                state.actions = actions;
+            }
+            Err(e) => errors.push(e),
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!(
+                "{}",
+                errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    pub async fn monitor(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.refresh().await {
+                warn!(error = %e, "Failed to refresh from the indexer management API");
+            }
+        }
+    }
+
+    async fn query<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        field: &str,
+    ) -> Result<T, anyhow::Error> {
+        let response = self
+            .client
+            .post(&self.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(json!({ "query": query }).to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let mut response: Value = serde_json::from_str(&response)?;
+
+        if let Some(errors) = response.get("errors") {
+            return Err(anyhow::anyhow!("{} query failed: {}", field, errors));
+        }
+        let data = response
+            .pointer_mut(&format!("/data/{}", field))
+            .map(Value::take)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected {} response: {}", field, response))?;
+        Ok(serde_json::from_value(data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use axum::{routing::post, Json, Router};
+
+    use super::*;
+
+    /// Management API stub, failing the indexing rules query while `fail_rules` is set
+    fn serve_management_api(fail_rules: Arc<AtomicBool>) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move |Json(request): Json<Value>| {
+                let fail_rules = fail_rules.clone();
+                async move {
+                    let query = request["query"].as_str().unwrap_or_default();
+                    Json(if query.contains("costModels") {
+                        json!({ "data": { "costModels": [
+                            { "deployment": "global", "model": "default => 0.00001;", "variables": null },
+                            { "deployment": "Qm1", "model": "default => 0.1;", "variables": "{}" },
+                        ] } })
+                    } else if query.contains("indexingRules") {
+                        match fail_rules.load(Ordering::SeqCst) {
+                            true => json!({ "errors": [{ "message": "Database unavailable" }] }),
+                            false => json!({ "data": { "indexingRules": [
+                                { "identifier": "global", "identifierType": "group", "decisionBasis": "rules" },
+                                { "identifier": "Qm1", "identifierType": "deployment", "decisionBasis": "always" },
+                            ] } }),
+                        }
+                    } else {
+                        json!({ "data": { "actions": [{
+                            "id": 1, "status": "queued", "type": "allocate", "deploymentID": "Qm1",
+                            "allocationID": null, "amount": "100", "source": "agent", "reason": "rules",
+                        }] } })
+                    })
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        url
+    }
+
+    #[tokio::test]
+    async fn resources_are_refreshed_independently() {
+        let fail_rules = Arc::new(AtomicBool::new(true));
+        let client = IndexerManagementClient::new(&serve_management_api(fail_rules.clone()));
+
+        let error = client.refresh().await.unwrap_err();
+        assert!(error.to_string().contains("indexingRules"));
+        assert_eq!(
+            client.cost_model("Qm1").unwrap().model.as_deref(),
+            Some("default => 0.1;")
+        );
+        assert_eq!(
+            client.cost_model("Qm2").unwrap().model.as_deref(),
+            Some("default => 0.00001;")
+        );
+        assert_eq!(client.actions().len(), 1);
+        assert!(client.indexing_rules().is_empty());
+
+        fail_rules.store(false, Ordering::SeqCst);
+        client.refresh().await.unwrap();
+        assert_eq!(client.indexing_rules().len(), 2);
+        assert_eq!(
+            client.indexing_rule("Qm1").unwrap().decision_basis,
+            "always"
+        );
+        assert_eq!(
+            client.indexing_rule("Qm2").unwrap().identifier,
+            GLOBAL_IDENTIFIER
+        );
+    }
+}
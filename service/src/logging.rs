@@ -0,0 +1,406 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use tokio::sync::broadcast;
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::MakeWriter,
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    reload,
+    util::{SubscriberInitExt, TryInitError},
+    EnvFilter, Layer, Registry,
+};
+
+use crate::{
+    config::{LogFormat, LogLevel},
+    telemetry,
+};
+
+/// Log lines kept in memory for operators connecting to the log stream
+const LOG_STREAM_BUFFER: usize = 1000;
+
+/// Handle to the global subscriber, to change its filter and follow its output at runtime
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+    stream: LogStream,
+}
+
+impl std::fmt::Debug for LogHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogHandle")
+            .field("filter", &self.filter())
+            .finish_non_exhaustive()
+    }
+}
+
+impl LogHandle {
+    /// Current filter directives
+    pub fn filter(&self) -> String {
+        self.filter
+            .with_current(|filter| filter.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Replace the filter directives, e.g. `info,service::graph_node=debug`
+    pub fn set_filter(&self, directives: &str) -> Result<(), anyhow::Error> {
+        let filter = EnvFilter::try_new(filter_directives(directives))?;
+        self.filter.reload(filter)?;
+        Ok(())
+    }
+
+    pub fn stream(&self) -> &LogStream {
+        &self.stream
+    }
+}
+
+/// Map the levels of directives through `LogLevel`, so that `fatal` and `module=fatal` are
+/// understood
+fn filter_directives(directives: &str) -> String {
+    let level = |level: &str| match LogLevel::from_str(level.trim(), true) {
+        Ok(level) => level.as_directive().to_string(),
+        Err(_) => level.trim().to_string(),
+    };
+    directives
+        .split(',')
+        .map(|directive| match directive.split_once('=') {
+            Some((target, target_level)) => format!("{}={}", target.trim(), level(target_level)),
+            None => level(directive),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Sets up tracing with the given format and filter directives, falling back to the `RUST_LOG`
+/// environment variable. Logs go to stdout, to daily files in `log_directory` if it is set,
+/// keeping the last `log_max_files` of them, and to the in-memory log stream. Spans are exported
+/// to `otlp_endpoint` if it is set.
+pub fn init_tracing(
+    format: LogFormat,
+    directives: Option<&str>,
+    log_directory: Option<PathBuf>,
+    log_max_files: usize,
+    otlp_endpoint: Option<&str>,
+) -> Result<LogHandle, anyhow::Error> {
+    let directives = directives
+        .map(str::to_string)
+        .or_else(|| std::env::var(EnvFilter::DEFAULT_ENV).ok())
+        .unwrap_or_default();
+    let (filter, filter_handle) =
+        reload::Layer::new(EnvFilter::try_new(filter_directives(&directives))?);
+
+    let stream = LogStream::new(LOG_STREAM_BUFFER);
+    let file = log_directory
+        .map(|directory| RollingFile::new(directory, "indexer-service.log", log_max_files))
+        .transpose()?
+        .map(|file| fmt_layer(format, file, false));
+
+    Registry::default()
+        .with(filter)
+        .with(fmt_layer(format, io::stdout, true))
+        .with(file)
+        .with(stream.clone())
+        .with(telemetry::otlp_layer(otlp_endpoint)?)
+        .try_init()
+        .map_err(|e: TryInitError| anyhow::anyhow!(e))?;
+
+    Ok(LogHandle {
+        filter: filter_handle,
+        stream,
+    })
+}
+
+fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match format {
+        LogFormat::Json => layer.json().boxed(),
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+    }
+}
+
+/// Event kept for the log stream, serialized to a JSON line only when it is read
+struct StreamEvent {
+    timestamp: DateTime<Utc>,
+    level: Level,
+    target: &'static str,
+    fields: Map<String, Value>,
+    spans: Vec<&'static str>,
+}
+
+impl StreamEvent {
+    fn to_line(&self) -> String {
+        json!({
+            "timestamp": self.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            "level": self.level.as_str(),
+            "target": self.target,
+            "fields": self.fields,
+            "spans": self.spans,
+        })
+        .to_string()
+    }
+}
+
+/// Fields of an event, as JSON values
+struct StreamFields<'a>(&'a mut Map<String, Value>);
+
+impl Visit for StreamFields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+}
+
+/// Recent log events and a channel of new ones as JSON lines. Events are kept unserialized,
+/// and only serialized for the channel while the stream is followed.
+#[derive(Clone)]
+pub struct LogStream {
+    recent: Arc<Mutex<VecDeque<Arc<StreamEvent>>>>,
+    capacity: usize,
+    sender: broadcast::Sender<String>,
+}
+
+impl LogStream {
+    fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        LogStream {
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            sender,
+        }
+    }
+
+    pub fn recent(&self) -> Vec<String> {
+        let recent: Vec<Arc<StreamEvent>> = self.recent.lock().unwrap().iter().cloned().collect();
+        recent.iter().map(|event| event.to_line()).collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.sender.subscribe()
+    }
+
+    fn push(&self, event: StreamEvent) {
+        let event = Arc::new(event);
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == self.capacity {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+        if self.sender.receiver_count() > 0 {
+            // The last receiver may have dropped meanwhile
+            let _ = self.sender.send(event.to_line());
+        }
+    }
+}
+
+impl<S> Layer<S> for LogStream
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = Map::new();
+        event.record(&mut StreamFields(&mut fields));
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| scope.from_root().map(|span| span.name()).collect())
+            .unwrap_or_default();
+        self.push(StreamEvent {
+            timestamp: Utc::now(),
+            level: *event.metadata().level(),
+            target: event.metadata().target(),
+            fields,
+            spans,
+        });
+    }
+}
+
+/// Log file in a directory, starting a new `<name>.<date>` file every day and deleting the
+/// oldest ones beyond `max_files`, or none if it is 0
+pub struct RollingFile {
+    directory: PathBuf,
+    name: String,
+    max_files: usize,
+    current: Mutex<(NaiveDate, File)>,
+}
+
+impl RollingFile {
+    fn new(directory: PathBuf, name: &str, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        let today = Utc::now().date_naive();
+        let file = Self::open(&directory, name, today)?;
+        let rolling_file = RollingFile {
+            directory,
+            name: name.to_string(),
+            max_files,
+            current: Mutex::new((today, file)),
+        };
+        rolling_file.prune()?;
+        Ok(rolling_file)
+    }
+
+    fn open(directory: &std::path::Path, name: &str, date: NaiveDate) -> io::Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(directory.join(format!("{}.{}", name, date.format("%Y-%m-%d"))))
+    }
+
+    /// Delete the oldest log files beyond `max_files`
+    fn prune(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return Ok(());
+        }
+        let prefix = format!("{}.", self.name);
+        let mut dates = fs::read_dir(&self.directory)?
+            .filter_map(|entry| {
+                let file_name = entry.ok()?.file_name().into_string().ok()?;
+                let date = file_name.strip_prefix(&prefix)?;
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+            })
+            .collect::<Vec<NaiveDate>>();
+        dates.sort_unstable_by(|a, b| b.cmp(a));
+        for date in dates.into_iter().skip(self.max_files) {
+            fs::remove_file(
+                self.directory
+                    .join(format!("{}{}", prefix, date.format("%Y-%m-%d"))),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Writer holding the current log file until the event is written
+pub struct RollingFileWriter<'a>(MutexGuard<'a, (NaiveDate, File)>);
+
+impl Write for RollingFileWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 .1.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0 .1.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for RollingFile {
+    type Writer = RollingFileWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let today = Utc::now().date_naive();
+        if current.0 != today {
+            // Keep writing to the previous file if the new one cannot be opened
+            if let Ok(file) = Self::open(&self.directory, &self.name, today) {
+                *current = (today, file);
+                // Old files are deleted again on the next rotation if this fails
+                let _ = self.prune();
+            }
+        }
+        RollingFileWriter(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_of_module_directives_are_mapped() {
+        assert_eq!(
+            filter_directives("fatal, service::graph_node=fatal,hyper=debug"),
+            "error,service::graph_node=error,hyper=debug"
+        );
+    }
+
+    #[test]
+    fn oldest_log_files_are_deleted() {
+        let directory =
+            std::env::temp_dir().join(format!("indexer-service-logs-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for date in ["2023-01-01", "2023-01-02", "2023-01-03"] {
+            File::create(directory.join(format!("indexer-service.log.{}", date))).unwrap();
+        }
+        File::create(directory.join("other.log.2023-01-01")).unwrap();
+
+        RollingFile::new(directory.clone(), "indexer-service.log", 2).unwrap();
+
+        let mut files = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<String>>();
+        files.sort();
+        fs::remove_dir_all(&directory).unwrap();
+        let today = Utc::now().date_naive().format("%Y-%m-%d");
+        assert_eq!(
+            files,
+            vec![
+                "indexer-service.log.2023-01-03".to_string(),
+                format!("indexer-service.log.{}", today),
+                "other.log.2023-01-01".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn recent_events_are_kept_while_the_stream_is_not_followed() {
+        let stream = LogStream::new(2);
+        let subscriber = Registry::default().with(stream.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("dropped");
+            tracing::info_span!("request").in_scope(|| tracing::info!(status = 200, "unfollowed"));
+            let mut receiver = stream.subscribe();
+            tracing::warn!("followed");
+            let line: Value = serde_json::from_str(&receiver.try_recv().unwrap()).unwrap();
+            assert_eq!(line["level"], "WARN");
+            assert_eq!(line["fields"]["message"], "followed");
+        });
+
+        let recent = stream
+            .recent()
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect::<Vec<Value>>();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0]["fields"]["message"], "unfollowed");
+        assert_eq!(recent[0]["fields"]["status"], 200);
+        assert_eq!(recent[0]["spans"], json!(["request"]));
+        assert_eq!(recent[1]["fields"]["message"], "followed");
+    }
+}
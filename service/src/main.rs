@@ -1,43 +1,80 @@
 use async_graphql::{EmptyMutation, EmptySubscription, Schema};
 use axum::{
-    error_handling::HandleErrorLayer,
-    http::{Method, StatusCode},
-    routing::get,
+    middleware,
+    routing::{any, get},
 };
-use axum::{routing::post, Extension, Router, Server};
+use axum::{routing::post, Extension, Router};
+use axum_server::Handle;
 use dotenvy::dotenv;
+use ethers::{
+    providers::{Http, Provider},
+    types::Address,
+};
 use model::QueryRoot;
 
-use std::{net::SocketAddr, str::FromStr, time::Duration};
-use tower::{BoxError, ServiceBuilder};
-use tower_http::cors::CorsLayer;
+use std::{
+    net::{IpAddr, SocketAddr},
+    num::NonZeroUsize,
+    sync::Arc,
+    time::Duration,
+};
 use tracing::info;
 
 use util::{package_version, shutdown_signal};
 
 use crate::{
-    config::Cli, metrics::handle_serve_metrics, query_processor::QueryProcessor, util::public_key,
+    allocation_monitor::AllocationMonitor,
+    common::{
+        chain::resolve_chain_context, database::create_pg_pool, indexer_error::IndexerErrorCode,
+        signer::load_operator_signer,
+    },
+    config::Cli,
+    ethereum::EthereumClient,
+    graph_node::{GraphNodeInstance, PoolOptions},
+    indexer_management::IndexerManagementClient,
+    metrics::handle_serve_metrics,
+    network_subgraph::NetworkSubgraphClient,
+    query_cache::QueryCache,
+    query_fee::allocations::AllocationReceiptManager,
+    query_processor::QueryProcessor,
+    readiness::{ProbeTargets, Readiness},
 };
 // use server::{ServerOptions, index, subgraph_queries, network_queries};
 
-use server::{routes, ServerOptions};
+use server::{
+    layers::{self, StartupGate},
+    listener::{self, TlsListener},
+    routes,
+    subscriptions::SubscriptionConnections,
+    ServerOptions,
+};
 
+mod allocation_monitor;
 mod common;
 mod config;
+mod ethereum;
 mod graph_node;
+mod indexer_management;
+mod logging;
 mod metrics;
 mod model;
+mod network_subgraph;
+mod query_cache;
 mod query_fee;
 mod query_processor;
+mod query_validator;
+mod readiness;
 mod server;
+mod status_query;
+mod telemetry;
 mod util;
 
 /// Create Indexer service App
 ///
 /// Initialization for server and Query processor
 ///
-/// Validate that graph-node instance is running for Query processor
-/// Validate that server is running with a health check
+/// Validate that graph-node and the other dependencies are reachable, and keep
+/// probing them for the readiness check
 ///
 /// Parse Requests received
 ///
@@ -50,75 +87,328 @@ async fn main() -> Result<(), std::io::Error> {
 
     // Parse basic configurations
     let config = Cli::args();
-    let release = package_version().expect("Failed to resolve for release version");
+    let logging = config.init_logging();
+    let release = package_version();
 
-    // Proper initiation of server, query processor
-    // server health check, graph-node instance connection check
-    let query_processor = QueryProcessor::new(
-        &config.indexer_infrastructure.graph_node_query_endpoint,
-        &config.network_subgraph.network_subgraph_endpoint,
+    // Connections to the indexer database, checked out by the readiness probes
+    let postgres = create_pg_pool(
+        &config
+            .postgres
+            .database_url()
+            .expect("Invalid Postgres settings"),
     );
 
+    // Probe graph-node, Postgres, the network subgraph and the Ethereum provider
+    // at startup and keep probing them to gate readiness
+    let readiness = Readiness::new(ProbeTargets {
+        graph_node_query_endpoints: config
+            .indexer_infrastructure
+            .graph_node_query_endpoints
+            .clone(),
+        graph_node_status_endpoint: config
+            .indexer_infrastructure
+            .graph_node_status_endpoint
+            .clone(),
+        postgres: postgres.clone(),
+        network_subgraph_endpoint: config.network_subgraph.network_subgraph_endpoint.clone(),
+        ethereum: config.ethereum.ethereum.clone(),
+    });
+    readiness.log_startup_check().await;
+    tokio::spawn(readiness.clone().monitor(Duration::from_millis(
+        config.indexer_infrastructure.readiness_check_interval,
+    )));
+
+    // Ethereum provider of the protocol network, checked against the configured chain
+    let provider = Provider::<Http>::try_from(config.ethereum.ethereum.as_str())
+        .expect("Invalid Ethereum provider URL")
+        .interval(Duration::from_millis(
+            config.ethereum.ethereum_polling_interval as u64,
+        ));
+
+    // TLS of the query and metrics servers, with certificates reloaded once renewed
+    let tls_reload_interval = Duration::from_millis(config.server.tls_reload_interval);
+    let query_tls = config
+        .server
+        .query_tls()
+        .expect("Invalid TLS settings for the query server")
+        .map(|files| TlsListener::new(files).expect("Failed to load TLS for the query server"));
+    let metrics_tls = config
+        .server
+        .metrics_tls()
+        .expect("Invalid TLS settings for the metrics server")
+        .map(|files| TlsListener::new(files).expect("Failed to load TLS for the metrics server"));
+    for tls in query_tls.iter().chain(metrics_tls.iter()) {
+        tokio::spawn(tls.clone().watch(tls_reload_interval));
+    }
+
     // Start indexer service basic metrics
     tokio::spawn(handle_serve_metrics(
-        String::from("0.0.0.0"),
-        config.indexer_infrastructure.metrics_port,
+        SocketAddr::new(
+            config
+                .server
+                .metrics_bind_address
+                .parse::<IpAddr>()
+                .expect("Invalid metrics bind address"),
+            config.indexer_infrastructure.metrics_port,
+        ),
+        metrics_tls,
+    ));
+
+    let cors = layers::cors_layer(&config.server).expect("Invalid CORS settings");
+    let max_body_size = config.server.max_body_size;
+    let request_timeouts = config.server.request_timeouts.clone();
+
+    // Liveness and readiness are served right away, the other routes once started up
+    let startup_gate = StartupGate::default();
+    let app = Router::new()
+        .route("/health", get(routes::basic::health))
+        .route("/ready", get(routes::basic::ready))
+        .fallback(any(StartupGate::forward))
+        .layer(Extension(startup_gate.clone()))
+        .layer(Extension(readiness.clone()))
+        .layer(middleware::from_fn(move |req, next| {
+            layers::limit_body(max_body_size, req, next)
+        }))
+        .layer(middleware::from_fn(move |req, next| {
+            request_timeouts.clone().enforce(req, next)
+        }))
+        .layer(cors)
+        .layer(middleware::from_fn(telemetry::trace_request));
+    // Client certificates are checked by route, as only some routes require them
+    let app = if query_tls.as_ref().is_some_and(|tls| tls.verifies_clients()) {
+        app.layer(middleware::from_fn(listener::require_client_certificate))
+    } else {
+        app
+    };
+
+    let addr = SocketAddr::new(
+        config
+            .server
+            .bind_address
+            .parse::<IpAddr>()
+            .expect("Invalid bind address"),
+        u16::try_from(config.indexer_infrastructure.port).expect("Invalid server port"),
+    );
+    // Stop accepting connections on shutdown, and wait for the open ones to finish
+    let handle = Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown_signal().await;
+            handle.graceful_shutdown(None);
+        }
+    });
+    info!(
+        tls = query_tls.is_some(),
+        "Initialized server app at {}", addr
+    );
+    let mut server = tokio::spawn(listener::serve(
+        app,
+        addr,
+        query_tls,
+        listener::http_config(&config.server),
+        listener::addr_incoming_config(&config.server),
+        handle,
     ));
+
+    // Chain ID and contract addresses, retried until the provider and the network
+    // subgraph are reachable
+    let chain = tokio::select! {
+        served = &mut server => {
+            telemetry::shutdown();
+            return served.expect("Query server failed");
+        }
+        chain = resolve_chain_context(
+            &config.ethereum,
+            &provider,
+            &config.network_subgraph.network_subgraph_endpoint,
+            config.network_subgraph.network_subgraph_auth_token.as_deref(),
+            &readiness,
+        ) => chain.expect("Invalid chain configuration"),
+    };
+
+    let operator_signer = load_operator_signer(&config.ethereum)
+        .await
+        .expect("Failed to initiate with operator wallet");
+    let indexer_address = config
+        .ethereum
+        .indexer_address
+        .parse::<Address>()
+        .expect("Invalid indexer address");
+    let ethereum = Arc::new(EthereumClient::new(
+        provider,
+        chain.chain_id,
+        chain.staking_address,
+        operator_signer.address(),
+        indexer_address,
+        config.network_subgraph.network_subgraph_endpoint.clone(),
+        config.network_subgraph.network_subgraph_auth_token.clone(),
+    ));
+    // Refuse to serve for an indexer the operator is not authorized for
+    match ethereum.check_operator().await {
+        Err(e) if matches!(e.code(), IndexerErrorCode::IE034) => {
+            panic!("Operator is not authorized for the indexer: {}", e)
+        }
+        // Logged as the indexer error is built, and checked again by the monitor
+        Err(_) => {}
+        Ok(()) => info!(
+            operator = ?ethereum.operator(),
+            indexer = ?ethereum.indexer(),
+            "Operator is authorized for the indexer"
+        ),
+    }
+    tokio::spawn(ethereum.clone().monitor(Duration::from_millis(
+        config.ethereum.ethereum_polling_interval as u64,
+    )));
+    tokio::spawn(ethereum.clone().monitor_operator(Duration::from_millis(
+        config.ethereum.operator_check_interval,
+    )));
+
+    // Proper initiation of server, query processor
+    let graph_node = GraphNodeInstance::new(
+        &config.indexer_infrastructure.graph_node_query_endpoints,
+        PoolOptions {
+            selection: config.indexer_infrastructure.graph_node_selection,
+            max_retries: config.indexer_infrastructure.graph_node_max_retries,
+            failure_threshold: config.indexer_infrastructure.graph_node_failure_threshold,
+            cooldown: Duration::from_millis(
+                config.indexer_infrastructure.graph_node_circuit_cooldown,
+            ),
+        },
+    );
+    // Optional cache of identical queries, invalidated as deployment heads move
+    let query_cache =
+        NonZeroUsize::new(config.indexer_infrastructure.query_cache_size).map(|capacity| {
+            let cache = Arc::new(QueryCache::new(
+                capacity,
+                Duration::from_millis(config.indexer_infrastructure.query_cache_ttl),
+                config
+                    .indexer_infrastructure
+                    .graph_node_status_endpoint
+                    .clone(),
+            ));
+            tokio::spawn(
+                cache.clone().track_heads(Duration::from_millis(
+                    config
+                        .indexer_infrastructure
+                        .query_cache_head_polling_interval,
+                )),
+            );
+            cache
+        });
+    // Network subgraph, preferably from the deployment indexed by the local graph-node
+    let network_subgraph = NetworkSubgraphClient::new(
+        graph_node.clone(),
+        config
+            .indexer_infrastructure
+            .graph_node_status_endpoint
+            .clone(),
+        config.network_subgraph.network_subgraph_deployment.clone(),
+        &config.network_subgraph.network_subgraph_endpoint,
+        config.network_subgraph.network_subgraph_max_lag,
+    );
+    tokio::spawn(network_subgraph.clone().monitor(Duration::from_millis(
+        config.network_subgraph.network_subgraph_health_interval,
+    )));
+    let allocation_monitor = AllocationMonitor::new(network_subgraph.clone(), indexer_address);
+    tokio::spawn(allocation_monitor.clone().monitor(Duration::from_millis(
+        config.network_subgraph.allocation_syncing_interval as u64,
+    )));
+
+    let query_processor = QueryProcessor::new(graph_node, network_subgraph, query_cache);
+    // Receipts of paid queries, signed by one of the trusted client signers
+    let receipt_manager = AllocationReceiptManager::new(
+        postgres,
+        config
+            .network_subgraph
+            .client_signers()
+            .expect("Invalid client signer addresses"),
+    );
+
+    // Cost models, indexing rules and actions from the indexer agent
+    let indexer_management_client =
+        IndexerManagementClient::new(&config.indexer_infrastructure.indexer_management_url);
+    tokio::spawn(
+        indexer_management_client
+            .clone()
+            .monitor(Duration::from_millis(
+                config
+                    .indexer_infrastructure
+                    .indexer_management_refresh_interval,
+            )),
+    );
+
     let service_options = ServerOptions::new(
         Some(config.indexer_infrastructure.port),
         release,
         query_processor,
-        config.indexer_infrastructure.free_query_auth_token,
+        config.auth_tokens().expect("Invalid auth tokens"),
+        config.indexer_infrastructure.require_status_auth,
+        config.indexer_infrastructure.free_query_limits,
+        config.network_subgraph.network_query_limits,
         config.indexer_infrastructure.graph_node_status_endpoint,
-        public_key(&config.ethereum.mnemonic).expect("Failed to initiate with operator wallet"),
-        config.network_subgraph.network_subgraph_auth_token,
+        operator_signer,
         config.network_subgraph.serve_network_subgraph,
+        chain.attestation_domain,
+        ethereum,
+        allocation_monitor,
+        indexer_management_client.clone(),
+        logging,
+        config.indexer_infrastructure.graph_node_ws_endpoint.clone(),
+        SubscriptionConnections::new(config.indexer_infrastructure.max_subscription_connections),
+        receipt_manager,
     );
 
-    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription).finish();
+    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(indexer_management_client)
+        .finish();
 
     info!("Initialized server options");
     let app = Router::new()
         .route("/", get(routes::basic::index))
-        .route("/health", get(routes::basic::health))
         .route("/version", get(routes::basic::version))
+        .route("/errors", get(routes::basic::errors))
+        .route(
+            "/admin/logs/filter",
+            get(routes::logs::log_filter).put(routes::logs::set_log_filter),
+        )
+        .route("/admin/logs/stream", get(routes::logs::log_stream))
+        .route(
+            "/admin/indexing-rules",
+            get(routes::management::indexing_rules),
+        )
+        .route("/admin/actions", get(routes::management::actions))
+        .route(
+            "/admin/client-signers",
+            get(routes::signers::client_signers).put(routes::signers::set_client_signers),
+        )
         .route(
             "/subgraphs/id/:id",
-            post(routes::subgraphs::subgraph_queries),
+            post(routes::subgraphs::subgraph_queries).get(routes::subgraphs::subgraph_get_queries),
+        )
+        .route(
+            "/subgraphs/id/:id/ws",
+            get(routes::subgraphs::subgraph_subscriptions),
+        )
+        .route(
+            "/subgraphs/name/*name",
+            post(routes::subgraphs::subgraph_name_queries)
+                .get(routes::subgraphs::subgraph_name_get_queries),
         )
         .route("/network", post(routes::network::network_queries))
         .route("/status", post(routes::status::status_queries))
+        .route("/status/poi", post(routes::status::poi_range_queries))
+        .route("/cost", post(routes::cost::cost_queries))
         .nest(
             "/operator",
             routes::basic::create_operator_server(service_options.clone()),
         )
         .layer(Extension(schema))
-        .layer(Extension(service_options.clone()))
-        .layer(CorsLayer::new().allow_methods([Method::GET, Method::POST]))
-        .layer(
-            ServiceBuilder::new()
-                .layer(HandleErrorLayer::new(|error: BoxError| async move {
-                    if error.is::<tower::timeout::error::Elapsed>() {
-                        Ok(StatusCode::REQUEST_TIMEOUT)
-                    } else {
-                        Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            format!("Unhandled internal error: {}", error),
-                        ))
-                    }
-                }))
-                .timeout(Duration::from_secs(10))
-                .into_inner(),
-        );
-
-    let addr = SocketAddr::from_str(&format!("0.0.0.0:{}", config.indexer_infrastructure.port))
-        .expect("Start server port");
-    info!("Initialized server app at {}", addr);
-    Server::bind(&addr)
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+        .layer(Extension(service_options.clone()));
+    startup_gate.open(app);
+
+    server.await.expect("Query server failed")?;
+    telemetry::shutdown();
 
     Ok(())
 }
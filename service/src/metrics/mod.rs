@@ -2,12 +2,18 @@ use autometrics::{encode_global_metrics, global_metrics_exporter};
 use axum::http::StatusCode;
 use axum::routing::get;
 use axum::Router;
+use axum_server::{AddrIncomingConfig, Handle, HttpConfig};
 use once_cell::sync::Lazy;
 use prometheus::{core::Collector, Registry};
-use prometheus::{linear_buckets, HistogramOpts, HistogramVec, IntCounterVec, Opts};
-use std::{net::SocketAddr, str::FromStr};
+use prometheus::{
+    exponential_buckets, linear_buckets, Gauge, HistogramOpts, HistogramVec, IntCounterVec,
+    IntGauge, IntGaugeVec, Opts,
+};
+use std::net::SocketAddr;
 use tracing::{debug, info};
 
+use crate::server::listener::{self, TlsListener};
+
 pub static QUERIES: Lazy<IntCounterVec> = Lazy::new(|| {
     let m = IntCounterVec::new(
         Opts::new("queries", "Incoming queries")
@@ -169,19 +175,176 @@ pub static CHANNEL_MESSAGE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
     m
 });
 
-#[allow(dead_code)]
 pub static INDEXER_ERROR: Lazy<IntCounterVec> = Lazy::new(|| {
     let m = IntCounterVec::new(
         Opts::new("indexer_error", "Indexer errors observed over time")
             .namespace("indexer")
             .subsystem("service"),
-        &["code"],
+        &["code", "severity"],
     )
     .expect("Failed to create indexer_error");
     prometheus::register(Box::new(m.clone())).expect("Failed to register indexer_error counter");
     m
 });
 
+pub static GRAPH_NODE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let m = IntCounterVec::new(
+        Opts::new(
+            "graph_node_requests",
+            "Requests sent to each graph-node query endpoint",
+        )
+        .namespace("indexer")
+        .subsystem("service"),
+        &["backend", "result"],
+    )
+    .expect("Failed to create graph_node_requests counters");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register graph_node_requests counter");
+    m
+});
+
+pub static GRAPH_NODE_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    let m = HistogramVec::new(
+        HistogramOpts::new(
+            "graph_node_request_duration",
+            "Duration of successful requests to each graph-node query endpoint",
+        )
+        .namespace("indexer")
+        .subsystem("service")
+        .buckets(exponential_buckets(0.005, 2.0, 12).unwrap()),
+        &["backend"],
+    )
+    .expect("Failed to create graph_node_request_duration histograms");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register graph_node_request_duration counter");
+    m
+});
+
+pub static GRAPH_NODE_BACKEND_HEALTHY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
+        Opts::new(
+            "graph_node_backend_healthy",
+            "Whether a graph-node query endpoint is in rotation (1) or circuit broken (0)",
+        )
+        .namespace("indexer")
+        .subsystem("service"),
+        &["backend"],
+    )
+    .expect("Failed to create graph_node_backend_healthy gauges");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register graph_node_backend_healthy gauge");
+    m
+});
+
+pub static QUERY_CACHE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let m = IntCounterVec::new(
+        Opts::new("query_cache_requests", "Query cache lookups by result")
+            .namespace("indexer")
+            .subsystem("service"),
+        &["deployment", "result"],
+    )
+    .expect("Failed to create query_cache_requests counters");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register query_cache_requests counter");
+    m
+});
+
+pub static AUTHENTICATED_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let m = IntCounterVec::new(
+        Opts::new(
+            "authenticated_requests",
+            "Requests presenting an auth token, by token name",
+        )
+        .namespace("indexer")
+        .subsystem("service"),
+        &["token"],
+    )
+    .expect("Failed to create authenticated_requests counters");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register authenticated_requests counter");
+    m
+});
+
+pub static ETHEREUM_BLOCK_NUMBER: Lazy<IntGauge> = Lazy::new(|| {
+    let m = IntGauge::with_opts(
+        Opts::new(
+            "ethereum_block_number",
+            "Latest block number of the Ethereum provider",
+        )
+        .namespace("indexer")
+        .subsystem("service"),
+    )
+    .expect("Failed to create ethereum_block_number gauge");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register ethereum_block_number gauge");
+    m
+});
+
+pub static OPERATOR_ETH_BALANCE: Lazy<Gauge> = Lazy::new(|| {
+    let m = Gauge::with_opts(
+        Opts::new("operator_eth_balance", "ETH balance of the operator")
+            .namespace("indexer")
+            .subsystem("service"),
+    )
+    .expect("Failed to create operator_eth_balance gauge");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register operator_eth_balance gauge");
+    m
+});
+
+pub static ACTIVE_SUBSCRIPTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
+        Opts::new(
+            "active_subscriptions",
+            "GraphQL subscriptions proxied to graph-node",
+        )
+        .namespace("indexer")
+        .subsystem("service"),
+        &["deployment"],
+    )
+    .expect("Failed to create active_subscriptions gauge");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register active_subscriptions gauge");
+    m
+});
+
+pub static SUBSCRIPTION_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
+        Opts::new(
+            "subscription_connections",
+            "Open subscription WebSocket connections by auth token",
+        )
+        .namespace("indexer")
+        .subsystem("service"),
+        &["token"],
+    )
+    .expect("Failed to create subscription_connections gauge");
+    prometheus::register(Box::new(m.clone()))
+        .expect("Failed to register subscription_connections gauge");
+    m
+});
+
+pub static BUILD_INFO: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
+        Opts::new(
+            "info",
+            "Build information of the running indexer service, always 1",
+        )
+        .namespace("indexer")
+        .subsystem("service"),
+        &[
+            "version",
+            "git_commit",
+            "build_time",
+            "rustc_version",
+            "native_version",
+        ],
+    )
+    .expect("Failed to create info gauge");
+    prometheus::register(Box::new(m.clone())).expect("Failed to register info gauge");
+    m
+});
+
 #[allow(dead_code)]
 pub static REGISTRY: Lazy<prometheus::Registry> = Lazy::new(prometheus::Registry::new);
 
@@ -212,6 +375,16 @@ pub fn start_metrics() {
             Box::new(QUERY_DURATION.clone()),
             Box::new(CHANNEL_MESSAGE_DURATION.clone()),
             Box::new(INDEXER_ERROR.clone()),
+            Box::new(GRAPH_NODE_REQUESTS.clone()),
+            Box::new(GRAPH_NODE_REQUEST_DURATION.clone()),
+            Box::new(GRAPH_NODE_BACKEND_HEALTHY.clone()),
+            Box::new(QUERY_CACHE_REQUESTS.clone()),
+            Box::new(AUTHENTICATED_REQUESTS.clone()),
+            Box::new(ETHEREUM_BLOCK_NUMBER.clone()),
+            Box::new(OPERATOR_ETH_BALANCE.clone()),
+            Box::new(ACTIVE_SUBSCRIPTIONS.clone()),
+            Box::new(SUBSCRIPTION_CONNECTIONS.clone()),
+            Box::new(BUILD_INFO.clone()),
         ],
     );
 }
@@ -227,21 +400,25 @@ pub async fn get_metrics() -> (StatusCode, String) {
 
 /// Run the API server as well as Prometheus and a traffic generator
 #[allow(dead_code)]
-pub async fn handle_serve_metrics(host: String, port: u16) {
+pub async fn handle_serve_metrics(addr: SocketAddr, tls: Option<TlsListener>) {
     // Set up the exporter to collect metrics
     let _exporter = global_metrics_exporter();
 
     let app = Router::new().route("/metrics", get(get_metrics));
-    let addr =
-        SocketAddr::from_str(&format!("{}:{}", host, port)).expect("Start Prometheus metrics");
-    let server = axum::Server::bind(&addr);
     info!(
         address = addr.to_string(),
+        tls = tls.is_some(),
         "Prometheus Metrics port exposed"
     );
 
-    server
-        .serve(app.into_make_service())
-        .await
-        .expect("Error starting example API server");
+    listener::serve(
+        app,
+        addr,
+        tls,
+        HttpConfig::default(),
+        AddrIncomingConfig::default(),
+        Handle::new(),
+    )
+    .await
+    .expect("Error starting example API server");
 }
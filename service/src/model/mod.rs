@@ -1,6 +1,8 @@
 use async_graphql::{Context, Object, Schema};
 use async_graphql::{EmptyMutation, EmptySubscription};
 
+use crate::indexer_management::{CostModel, IndexerManagementClient};
+
 pub(crate) type ServiceSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
 
 pub(crate) struct QueryRoot;
@@ -10,4 +12,20 @@ impl QueryRoot {
     async fn hello(&self, _ctx: &Context<'_>) -> &'static str {
         "blahblahblaaaaaa"
     }
+
+    /// Cost model of a deployment, from the indexer management API
+    async fn cost_model(&self, ctx: &Context<'_>, deployment: String) -> Option<CostModel> {
+        ctx.data_unchecked::<IndexerManagementClient>()
+            .cost_model(&deployment)
+    }
+
+    /// Cost models of the given deployments, or of every deployment with a cost model
+    async fn cost_models(
+        &self,
+        ctx: &Context<'_>,
+        deployments: Option<Vec<String>>,
+    ) -> Vec<CostModel> {
+        ctx.data_unchecked::<IndexerManagementClient>()
+            .cost_models(deployments.as_deref())
+    }
 }
@@ -0,0 +1,153 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use reqwest::{header, Client, Url};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::{graph_node::GraphNodeInstance, query_processor::UnattestedQueryResult};
+
+/// Network subgraph queried from the deployment indexed by the local graph-node when it is
+/// configured, healthy and caught up, and from the remote endpoint otherwise
+#[derive(Debug, Clone)]
+pub struct NetworkSubgraphClient {
+    client: Client,
+    graph_node: GraphNodeInstance,
+    graph_node_status_endpoint: String,
+    local_deployment: Option<String>,
+    remote_endpoint: Url,
+    /// Blocks the local deployment may lag behind the chain head and still be used
+    max_lag: u64,
+    local_usable: Arc<AtomicBool>,
+}
+
+impl NetworkSubgraphClient {
+    pub fn new(
+        graph_node: GraphNodeInstance,
+        graph_node_status_endpoint: String,
+        local_deployment: Option<String>,
+        remote_endpoint: &str,
+        max_lag: u64,
+    ) -> Self {
+        NetworkSubgraphClient {
+            client: Client::new(),
+            graph_node,
+            graph_node_status_endpoint,
+            local_deployment,
+            remote_endpoint: Url::parse(remote_endpoint)
+                .expect("Could not parse network subgraph endpoint"),
+            max_lag,
+            local_usable: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Local deployment, if it is currently used to serve network subgraph queries
+    fn local(&self) -> Option<&str> {
+        self.local_deployment
+            .as_deref()
+            .filter(|_| self.local_usable.load(Ordering::Relaxed))
+    }
+
+    pub async fn query(&self, body: String) -> Result<UnattestedQueryResult, reqwest::Error> {
+        if let Some(deployment) = self.local() {
+            match self
+                .graph_node
+                .subgraph_query(deployment, body.clone())
+                .await
+            {
+                Ok(response) => {
+                    // Network subgraph responses are never attested
+                    return Ok(UnattestedQueryResult {
+                        attestable: false,
+                        ..response
+                    });
+                }
+                Err(e) => warn!(
+                    error = %e,
+                    "Failed to query the local network subgraph, falling back to the remote endpoint"
+                ),
+            }
+        }
+
+        self.graph_node
+            .network_query(self.remote_endpoint.clone(), body)
+            .await
+    }
+
+    /// Keep track of whether the local deployment is healthy and caught up with the chain
+    pub async fn monitor(self, interval: Duration) {
+        let deployment = match &self.local_deployment {
+            Some(deployment) => deployment.clone(),
+            None => return,
+        };
+
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            let usable = match self.local_lag(&deployment).await {
+                Ok(Some(lag)) if lag <= self.max_lag => true,
+                Ok(Some(lag)) => {
+                    debug!(deployment = %deployment, lag, "Local network subgraph is lagging");
+                    false
+                }
+                Ok(None) => {
+                    debug!(deployment = %deployment, "Local network subgraph is not healthy");
+                    false
+                }
+                Err(e) => {
+                    warn!(error = %e, "Failed to check the local network subgraph status");
+                    false
+                }
+            };
+
+            if self.local_usable.swap(usable, Ordering::Relaxed) != usable {
+                info!(
+                    deployment = %deployment,
+                    local = usable,
+                    "Switched network subgraph source"
+                );
+            }
+        }
+    }
+
+    /// Blocks the local deployment is behind the chain head, `None` if it is not healthy
+    async fn local_lag(&self, deployment: &str) -> Result<Option<u64>, anyhow::Error> {
+        let query = json!({
+            "query": "query status($subgraphs: [String!]) { indexingStatuses(subgraphs: $subgraphs) { health chains { latestBlock { number } chainHeadBlock { number } } } }",
+            "variables": { "subgraphs": [deployment] },
+        });
+        let response = self
+            .client
+            .post(&self.graph_node_status_endpoint)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(query.to_string())
+            .send()
+            .await?
+            .text()
+            .await?;
+        let response: Value = serde_json::from_str(&response)?;
+
+        let status = match response.pointer("/data/indexingStatuses/0") {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+        if status.get("health").and_then(|health| health.as_str()) != Some("healthy") {
+            return Ok(None);
+        }
+        let block =
+            |pointer: &str| -> Option<u64> { status.pointer(pointer)?.as_str()?.parse().ok() };
+        match (
+            block("/chains/0/latestBlock/number"),
+            block("/chains/0/chainHeadBlock/number"),
+        ) {
+            (Some(latest), Some(head)) => Ok(Some(head.saturating_sub(latest))),
+            _ => Ok(None),
+        }
+    }
+}
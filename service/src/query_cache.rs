@@ -0,0 +1,278 @@
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+use reqwest::{header, Client};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::{metrics::QUERY_CACHE_REQUESTS, query_processor::UnattestedQueryResult};
+
+/// Identifies a query response: same deployment, same document, same operation and same
+/// variables
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    deployment: String,
+    query: String,
+    operation_name: String,
+    variables: String,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    response: UnattestedQueryResult,
+    /// Deployment head when the query was sent, the entry is stale once the head moves
+    block: Option<u64>,
+    inserted_at: Instant,
+}
+
+/// In-memory LRU cache of graph-node responses, invalidated when a deployment head moves
+///
+/// Cached responses keep the exact bytes returned by graph-node so that attestations
+/// created for them stay valid.
+#[derive(Debug)]
+pub struct QueryCache {
+    entries: Mutex<LruCache<CacheKey, CacheEntry>>,
+    heads: RwLock<HashMap<String, u64>>,
+    ttl: Duration,
+    client: Client,
+    graph_node_status_endpoint: String,
+}
+
+impl QueryCache {
+    pub fn new(capacity: NonZeroUsize, ttl: Duration, graph_node_status_endpoint: String) -> Self {
+        QueryCache {
+            entries: Mutex::new(LruCache::new(capacity)),
+            heads: RwLock::new(HashMap::new()),
+            ttl,
+            client: Client::new(),
+            graph_node_status_endpoint,
+        }
+    }
+
+    /// Cache key of a query body, `None` if the body cannot be normalized
+    pub fn key(deployment: &str, body: &str) -> Option<CacheKey> {
+        let body: Value = serde_json::from_str(body).ok()?;
+        let query = body.get("query")?.as_str()?;
+        // Formatting the parsed document drops whitespace, comments and formatting differences
+        let query = graphql_parser::parse_query::<&str>(query).ok()?.to_string();
+        // JSON objects keep their keys sorted, so equal values serialize to the same string
+        let canonical = |field: &str| match body.get(field) {
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        };
+        Some(CacheKey {
+            deployment: deployment.to_string(),
+            query,
+            operation_name: canonical("operationName"),
+            variables: canonical("variables"),
+        })
+    }
+
+    /// Latest known head of a deployment
+    pub fn head(&self, deployment: &str) -> Option<u64> {
+        self.heads.read().unwrap().get(deployment).copied()
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<UnattestedQueryResult> {
+        let head = self.head(&key.deployment);
+        let mut entries = self.entries.lock().unwrap();
+        let fresh = match entries.get(key) {
+            Some(entry) => entry.block == head && entry.inserted_at.elapsed() < self.ttl,
+            None => false,
+        };
+
+        let result = if fresh {
+            entries.get(key).map(|entry| entry.response.clone())
+        } else {
+            entries.pop(key);
+            None
+        };
+
+        QUERY_CACHE_REQUESTS
+            .with_label_values(&[
+                key.deployment.as_str(),
+                if result.is_some() { "hit" } else { "miss" },
+            ])
+            .inc();
+        result
+    }
+
+    /// Store a response obtained while the deployment head was at `block`
+    pub fn insert(&self, key: CacheKey, response: UnattestedQueryResult, block: Option<u64>) {
+        // Responses with errors may be transient, only successful responses are reused
+        let has_errors = serde_json::from_str::<Value>(&response.graphql_response)
+            .map(|value| value.get("errors").is_some())
+            .unwrap_or(true);
+        if has_errors {
+            return;
+        }
+
+        self.entries.lock().unwrap().put(
+            key,
+            CacheEntry {
+                response,
+                block,
+                inserted_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Follow the heads of the cached deployments through the graph-node status endpoint
+    pub async fn track_heads(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            let deployments: HashSet<String> = self
+                .entries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(key, _)| key.deployment.clone())
+                .collect();
+            if deployments.is_empty() {
+                continue;
+            }
+
+            match self.fetch_heads(deployments.into_iter().collect()).await {
+                Ok(heads) => self.update_heads(heads),
+                Err(e) => warn!(error = %e, "Failed to fetch deployment heads for the query cache"),
+            }
+        }
+    }
+
+    fn update_heads(&self, heads: HashMap<String, u64>) {
+        let moved: Vec<String> = {
+            let mut known = self.heads.write().unwrap();
+            let moved = heads
+                .iter()
+                .filter(|(deployment, block)| known.get(*deployment) != Some(*block))
+                .map(|(deployment, _)| deployment.clone())
+                .collect();
+            known.extend(heads);
+            moved
+        };
+        if moved.is_empty() {
+            return;
+        }
+
+        // Drop entries of deployments that moved on to a new block
+        let mut entries = self.entries.lock().unwrap();
+        let stale: Vec<CacheKey> = entries
+            .iter()
+            .filter(|(key, _)| moved.contains(&key.deployment))
+            .map(|(key, _)| key.clone())
+            .collect();
+        debug!(
+            deployments = moved.len(),
+            entries = stale.len(),
+            "Invalidating cached queries after deployment head changes"
+        );
+        for key in stale {
+            entries.pop(&key);
+        }
+    }
+
+    async fn fetch_heads(
+        &self,
+        deployments: Vec<String>,
+    ) -> Result<HashMap<String, u64>, anyhow::Error> {
+        let query = json!({
+            "query": "query heads($subgraphs: [String!]) { indexingStatuses(subgraphs: $subgraphs) { subgraph chains { latestBlock { number } } } }",
+            "variables": { "subgraphs": deployments },
+        });
+        let response = self
+            .client
+            .post(&self.graph_node_status_endpoint)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(query.to_string())
+            .send()
+            .await?
+            .text()
+            .await?;
+        let response: Value = serde_json::from_str(&response)?;
+
+        let statuses = response
+            .pointer("/data/indexingStatuses")
+            .and_then(|statuses| statuses.as_array())
+            .ok_or_else(|| {
+                anyhow::anyhow!("Unexpected indexing statuses response: {}", response)
+            })?;
+        Ok(statuses
+            .iter()
+            .filter_map(|status| {
+                let deployment = status.get("subgraph")?.as_str()?.to_string();
+                let block = status
+                    .pointer("/chains/0/latestBlock/number")?
+                    .as_str()?
+                    .parse()
+                    .ok()?;
+                Some((deployment, block))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str =
+        "query blocks { _meta { block { number } } } query deployment { _meta { deployment } }";
+
+    fn body(operation_name: &str) -> String {
+        json!({ "query": DOCUMENT, "operationName": operation_name }).to_string()
+    }
+
+    fn response(data: &str) -> UnattestedQueryResult {
+        UnattestedQueryResult {
+            graphql_response: json!({ "data": data }).to_string(),
+            attestable: true,
+        }
+    }
+
+    #[test]
+    fn operations_of_one_document_are_cached_separately() {
+        let cache = QueryCache::new(
+            NonZeroUsize::new(10).unwrap(),
+            Duration::from_secs(60),
+            String::from("http://localhost:8030/graphql"),
+        );
+        let blocks = QueryCache::key("Qmdeployment", &body("blocks")).unwrap();
+        let deployment = QueryCache::key("Qmdeployment", &body("deployment")).unwrap();
+        assert_ne!(blocks, deployment);
+
+        cache.insert(blocks.clone(), response("blocks"), None);
+        cache.insert(deployment.clone(), response("deployment"), None);
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+        assert_eq!(
+            cache.get(&blocks).unwrap().graphql_response,
+            response("blocks").graphql_response
+        );
+        assert_eq!(
+            cache.get(&deployment).unwrap().graphql_response,
+            response("deployment").graphql_response
+        );
+    }
+
+    #[test]
+    fn variables_are_compared_canonically() {
+        let key = |variables: &str| {
+            QueryCache::key(
+                "Qmdeployment",
+                &format!(
+                    r#"{{"query": "{{ _meta {{ deployment }} }}", "variables": {}}}"#,
+                    variables
+                ),
+            )
+            .unwrap()
+        };
+        assert_eq!(key(r#"{"a": 1, "b": 2}"#), key(r#"{ "b": 2, "a": 1 }"#));
+        assert_ne!(key(r#"{"a": 1}"#), key(r#"{"a": 2}"#));
+    }
+}
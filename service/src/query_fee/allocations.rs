@@ -4,48 +4,151 @@ use crate::common::{
 };
 
 use async_trait::async_trait;
-use bigdecimal::BigDecimal;
+use bigdecimal::{num_bigint::BigInt, BigDecimal, Num};
 // use ethers::types::Address;
 use super::ReceiptManager;
 use crate::common::{database::PgPool, indexer_error::indexer_error};
 
 use ethers_core::utils::hex;
-use native::signature_verification::SignatureVerifier;
+use native::signature_verification::{AuthorizedSigner, SignatureVerifier};
 
+use lazy_static::lazy_static;
 use regex::Regex;
 use secp256k1::recovery::{RecoverableSignature, RecoveryId};
 
-use std::{collections::HashMap, str::FromStr, sync::Arc};
+use std::{collections::HashMap, fmt, sync::Arc};
+use tracing::instrument;
 
 type QueryFees = HashMap<String, HashMap<String, BigDecimal>>;
 
 // Takes a valid big-endian hexadecimal string and parses it as a U256
 fn read_number(data: &str, start: usize, end: usize) -> BigDecimal {
-    let hex_string = format!("0x{}", &data[start..end]);
-    BigDecimal::from_str(&hex_string).unwrap()
+    BigDecimal::from(BigInt::from_str_radix(&data[start..end], 16).unwrap())
 }
 
 static ALLOCATION_RECEIPT_VALIDATOR: &str = "^[0-9A-Fa-f]{264}$";
 
-async fn validate_signature(
-    signer: &SignatureVerifier,
-    receipt_data: &str,
-) -> Result<String, IndexerError> {
-    let message = &receipt_data[0..134].as_bytes();
-    //TODO: recover signature properly
-    let signature = RecoverableSignature::from_compact(
-        &hex::decode(&receipt_data[134..264]).unwrap(),
-        RecoveryId::from_i32(0).unwrap(),
-    )
-    .unwrap();
-
-    if signer.verify(message, &signature).is_err() {
-        let _code = IndexerErrorCode::IE031;
+lazy_static! {
+    static ref ALLOCATION_RECEIPT_REGEX: Regex = Regex::new(ALLOCATION_RECEIPT_VALIDATOR).unwrap();
+}
+
+/// Split a receipt into the signed receipt data and its signature
+fn parse_signature(receipt_data: &str) -> Result<(Vec<u8>, RecoverableSignature), IndexerError> {
+    let message =
+        hex::decode(&receipt_data[0..134]).map_err(|_| indexer_error(IndexerErrorCode::IE031))?;
+    let signature =
+        hex::decode(&receipt_data[134..264]).map_err(|_| indexer_error(IndexerErrorCode::IE031))?;
+
+    // Signatures are encoded as r || s || v, with v either 27/28 or 0/1
+    let recovery_id = match signature[64] {
+        v @ 27..=28 => v - 27,
+        v => v,
+    };
+    let recovery_id = RecoveryId::from_i32(recovery_id as i32)
+        .map_err(|_| indexer_error(IndexerErrorCode::IE031))?;
+    let signature = RecoverableSignature::from_compact(&signature[0..64], recovery_id)
+        .map_err(|_| indexer_error(IndexerErrorCode::IE031))?;
+
+    Ok((message, signature))
+}
+
+/// Receipt signed by one of the authorized signers, ready to be recorded
+pub struct VerifiedReceipt {
+    receipt_data: String,
+    signature: String,
+    /// Authorized signer that signed the receipt
+    sender: String,
+}
 
+impl VerifiedReceipt {
+    fn new(
+        receipt_data: String,
+        verified: Result<Option<[u8; 20]>, &'static str>,
+    ) -> Result<Self, IndexerError> {
+        match verified {
+            Ok(Some(sender)) => Ok(VerifiedReceipt {
+                signature: format!("0x{}", &receipt_data[134..264]),
+                sender: format!("0x{}", hex::encode(sender)),
+                receipt_data,
+            }),
+            _ => Err(indexer_error(IndexerErrorCode::IE031)),
+        }
+    }
+}
+
+/// Validate a receipt and split it into the signed receipt data and its signature
+fn parse_receipt(receipt_data: &str) -> Result<(Vec<u8>, RecoverableSignature), IndexerError> {
+    // Security: Input validation
+    if !ALLOCATION_RECEIPT_REGEX.is_match(receipt_data) {
         return Err(indexer_error(IndexerErrorCode::IE031));
     }
+    parse_signature(receipt_data)
+}
+
+/// Check that a receipt is signed by one of the authorized signers. Signatures are checked
+/// outside of the receipt manager, so that concurrent queries do not wait on each other.
+#[instrument(name = "receipt_validation", skip_all)]
+pub fn verify_receipt(
+    verifier: &SignatureVerifier,
+    receipt_data: String,
+) -> Result<VerifiedReceipt, IndexerError> {
+    // TODO: (Security) Additional validations are required to remove trust from
+    // the Gateway which are deferred until we can fully remove trust which requires:
+    //   * A receiptID based routing solution so that some invariants can be tested
+    //     in memory instead of hitting the database for performance (eg: collateral,
+    //     and that fees are increasing).
+    //   * A ZKP to ensure all receipts can be collected without running out of gas.
+    //
+    // Validations include:
+    //   * The address corresponds to an *unresolved* transfer.
+    //   * The unresolved transfer has sufficient collateral to pay for the query.
+    //   * Recovering the signature for the binary data in chars 20..56 = the specified address.
+    //   * The increase in fee amount from the last known valid state covers the cost of the query
+    //   * This receipt ID is not being "forked" by concurrent usage.
 
-    Ok(format!("0x{}", &receipt_data[134..264]))
+    let (message, signature) = parse_receipt(&receipt_data)?;
+    let verified = verifier.verify_signer(&message, &signature);
+    VerifiedReceipt::new(receipt_data, verified)
+}
+
+/// Check a burst of receipts at once, verifying their signatures in parallel on the
+/// blocking thread pool. Results are returned in the order of `receipts`.
+#[instrument(name = "receipt_validation", skip_all, fields(receipts = receipts.len()))]
+pub async fn verify_receipts(
+    verifier: Arc<SignatureVerifier>,
+    receipts: Vec<String>,
+) -> Vec<Result<VerifiedReceipt, IndexerError>> {
+    let parsed: Vec<Result<(Vec<u8>, RecoverableSignature), IndexerError>> = receipts
+        .iter()
+        .map(|receipt_data| parse_receipt(receipt_data))
+        .collect();
+
+    let signed: Vec<(Vec<u8>, RecoverableSignature)> = parsed
+        .iter()
+        .filter_map(|parsed| parsed.as_ref().ok().cloned())
+        .collect();
+    let count = signed.len();
+    let mut verified = tokio::task::spawn_blocking(move || verifier.verify_batch(&signed))
+        .await
+        .unwrap_or_else(|_| vec![Err("Signature verification failed"); count])
+        .into_iter();
+
+    receipts
+        .into_iter()
+        .zip(parsed)
+        .map(|(receipt_data, parsed)| {
+            parsed?;
+            let verified = verified
+                .next()
+                .unwrap_or(Err("Missing signature verification result"));
+            VerifiedReceipt::new(receipt_data, verified)
+        })
+        .collect()
+}
+
+/// ID of the allocation a receipt pays for, as a lowercase `0x` prefixed address
+pub fn receipt_allocation_id(receipt_data: &str) -> String {
+    format!("0x{}", receipt_data[0..40].to_ascii_lowercase())
 }
 
 // #[derive(SimpleObject)]
@@ -54,6 +157,8 @@ struct AllocationReceipt {
     allocation: Address,
     fees: BigDecimal,
     signature: String,
+    /// Authorized signer that signed the receipt
+    sender: String,
 }
 
 pub struct AllocationReceiptManager {
@@ -61,7 +166,16 @@ pub struct AllocationReceiptManager {
     // query_fee_models: QueryFeeModels,
     cache: HashMap<String, Arc<AllocationReceipt>>,
     flush_queue: Vec<String>,
-    allocation_receipt_verifier: SignatureVerifier,
+    allocation_receipt_verifier: Arc<SignatureVerifier>,
+}
+
+impl fmt::Debug for AllocationReceiptManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllocationReceiptManager")
+            .field("receipts", &self.cache.len())
+            .field("signers", &self.allocation_receipt_verifier.signers().len())
+            .finish_non_exhaustive()
+    }
 }
 
 #[async_trait]
@@ -70,38 +184,8 @@ impl ReceiptManager for AllocationReceiptManager {
         &mut self,
         receipt_data: String,
     ) -> Result<(String, Address, BigDecimal), IndexerError> {
-        let allocation_receipt_validator = Regex::new("^[0-9A-Fa-f]{264}$").unwrap();
-        // Security: Input validation
-        if !allocation_receipt_validator.is_match(&receipt_data) {
-            return Err(indexer_error(IndexerErrorCode::IE031));
-        }
-
-        // TODO: (Security) Additional validations are required to remove trust from
-        // the Gateway which are deferred until we can fully remove trust which requires:
-        //   * A receiptID based routing solution so that some invariants can be tested
-        //     in memory instead of hitting the database for performance (eg: collateral,
-        //     and that fees are increasing).
-        //   * A ZKP to ensure all receipts can be collected without running out of gas.
-        //
-        // Validations include:
-        //   * The address corresponds to an *unresolved* transfer.
-        //   * The unresolved transfer has sufficient collateral to pay for the query.
-        //   * Recovering the signature for the binary data in chars 20..56 = the specified address.
-        //   * The increase in fee amount from the last known valid state covers the cost of the query
-        //   * This receipt ID is not being "forked" by concurrent usage.
-
-        let receipt = self.parse_allocation_receipt(&receipt_data)?;
-        let signature =
-            validate_signature(&self.allocation_receipt_verifier, &receipt_data).await?;
-
-        self.queue(AllocationReceipt {
-            id: receipt.0.clone(),
-            allocation: receipt.1.clone(),
-            fees: receipt.2.clone(),
-            signature,
-        });
-
-        Ok(receipt)
+        let receipt = verify_receipt(&self.allocation_receipt_verifier, receipt_data)?;
+        self.record(receipt)
     }
 }
 
@@ -110,25 +194,40 @@ impl AllocationReceiptManager {
         sequelize: PgPool,
         // query_fee_models: QueryFeeModels,
         // logger: Logger,
-        client_signer_address: Address,
+        client_signers: Vec<AuthorizedSigner>,
     ) -> Self {
-        let addr = client_signer_address.as_bytes();
-        let fixed_bytes: [u8; 20] = {
-            let mut array = [0u8; 20]; // create a mutable byte array of size 20
-            let len = addr.len().min(20); // get the length of the byte array, capped at 20
-            array[..len].copy_from_slice(&addr[..len]); // copy bytes from byte array to fixed byte array
-            array
-        };
-
         Self {
             sequelize,
             // query_fee_models,
             cache: HashMap::new(),
             flush_queue: Vec::new(),
-            allocation_receipt_verifier: SignatureVerifier::new(fixed_bytes),
+            allocation_receipt_verifier: Arc::new(SignatureVerifier::new(client_signers)),
         }
     }
 
+    /// Verifier of the receipt signatures, usable without holding the receipt manager
+    pub fn verifier(&self) -> Arc<SignatureVerifier> {
+        self.allocation_receipt_verifier.clone()
+    }
+
+    /// Queue a receipt once its signature has been verified
+    pub fn record(
+        &mut self,
+        receipt: VerifiedReceipt,
+    ) -> Result<(String, Address, BigDecimal), IndexerError> {
+        let parsed = self.parse_allocation_receipt(&receipt.receipt_data)?;
+
+        self.queue(AllocationReceipt {
+            id: parsed.0.clone(),
+            allocation: parsed.1.clone(),
+            fees: parsed.2.clone(),
+            signature: receipt.signature,
+            sender: receipt.sender,
+        });
+
+        Ok(parsed)
+    }
+
     fn parse_allocation_receipt(
         &self,
         receipt_data: &str,
@@ -172,3 +271,111 @@ impl AllocationReceiptManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use diesel::{
+        r2d2::{ConnectionManager, Pool},
+        PgConnection,
+    };
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+    use sha3::{Digest, Keccak256};
+
+    use super::*;
+
+    fn signer_address(key: &SecretKey) -> [u8; 20] {
+        let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), key);
+        let hash = Keccak256::digest(&public_key.serialize_uncompressed()[1..]);
+        hash[12..].try_into().unwrap()
+    }
+
+    /// Receipt for an allocation, encoded as `allocation || fees || id || r || s || v`
+    fn signed_receipt(key: &SecretKey, fees: u64) -> String {
+        let mut message = vec![0x42; 20];
+        message.extend_from_slice(&[0; 24]);
+        message.extend_from_slice(&fees.to_be_bytes());
+        message.extend_from_slice(&[0x07; 15]);
+
+        let hash = Message::from_slice(&Keccak256::digest(&message)).unwrap();
+        let (recovery_id, signature) = Secp256k1::signing_only()
+            .sign_recoverable(&hash, key)
+            .serialize_compact();
+        format!(
+            "{}{}{:02x}",
+            hex::encode(&message),
+            hex::encode(signature),
+            recovery_id.to_i32() + 27
+        )
+    }
+
+    fn manager(client_signers: Vec<AuthorizedSigner>) -> AllocationReceiptManager {
+        // Never connected to, receipts are only queued in memory
+        let pool =
+            Pool::builder().min_idle(Some(0)).build_unchecked(
+                ConnectionManager::<PgConnection>::new("postgres://localhost"),
+            );
+        AllocationReceiptManager::new(pool, client_signers)
+    }
+
+    #[tokio::test]
+    async fn receipts_of_rotated_in_signers_are_accepted() {
+        let old_key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let new_key = SecretKey::from_slice(&[0x22; 32]).unwrap();
+        let mut manager = manager(vec![AuthorizedSigner::new(signer_address(&old_key))]);
+
+        let receipt = signed_receipt(&new_key, 100);
+        assert!(manager.add(receipt.clone()).await.is_err());
+
+        manager.verifier().set_signers(vec![
+            AuthorizedSigner::new(signer_address(&old_key)),
+            AuthorizedSigner::new(signer_address(&new_key)),
+        ]);
+        let (id, _, fees) = manager.add(receipt).await.unwrap();
+        assert_eq!(fees, BigDecimal::from(100));
+        assert_eq!(
+            manager.cache[&id].sender,
+            format!("0x{}", hex::encode(signer_address(&new_key)))
+        );
+
+        // Receipts of the signer rotated out are still accepted until it is removed
+        let (id, _, _) = manager.add(signed_receipt(&old_key, 200)).await.unwrap();
+        assert_eq!(
+            manager.cache[&id].sender,
+            format!("0x{}", hex::encode(signer_address(&old_key)))
+        );
+        manager
+            .verifier()
+            .set_signers(vec![AuthorizedSigner::new(signer_address(&new_key))]);
+        assert!(manager.add(signed_receipt(&old_key, 300)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn burst_receipts_are_verified_before_being_recorded() {
+        let key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let unknown_key = SecretKey::from_slice(&[0x33; 32]).unwrap();
+        let mut manager = manager(vec![AuthorizedSigner::new(signer_address(&key))]);
+
+        let verified = verify_receipts(
+            manager.verifier(),
+            vec![
+                signed_receipt(&key, 100),
+                signed_receipt(&unknown_key, 200),
+                "not a receipt".to_string(),
+                signed_receipt(&key, 300),
+            ],
+        )
+        .await;
+        assert_eq!(
+            verified.iter().map(Result::is_ok).collect::<Vec<bool>>(),
+            vec![true, false, false, true]
+        );
+
+        let recorded = verified
+            .into_iter()
+            .flatten()
+            .map(|receipt| manager.record(receipt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(recorded[1].2, BigDecimal::from(300));
+        assert_eq!(manager.cache[&recorded[1].0].fees, BigDecimal::from(300));
+    }
+}
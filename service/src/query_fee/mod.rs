@@ -11,7 +11,7 @@ pub mod allocations;
 // pub struct ReceiptManager;
 
 #[async_trait]
-trait ReceiptManager {
+pub trait ReceiptManager {
     async fn add(
         &mut self,
         receipt_data: String,
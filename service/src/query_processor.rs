@@ -1,10 +1,15 @@
-use ethers_core::abi::AbiEncode;
+use ethers_core::utils::hex;
 use log::error;
+use native::attestation::{Attestation, AttestationSigner};
 use regex::Regex;
-use reqwest::{Client, Url};
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::trace;
 
-use crate::graph_node::GraphNodeInstance;
+use crate::{
+    graph_node::GraphNodeInstance, network_subgraph::NetworkSubgraphClient, query_cache::QueryCache,
+};
 
 /// Subgraph identifier type: Subgraph name with field 'value'
 pub struct SubgraphName {
@@ -39,7 +44,7 @@ pub fn multihash_check() -> Regex {
 }
 
 /// Subgraph identifier type: SubgraphDeploymentID with field 'value'
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SubgraphDeploymentID {
     // Hexadecimal (bytes32) representation of the subgraph deployment Id
     value: String,
@@ -53,16 +58,35 @@ impl SubgraphDeploymentID {
         SubgraphDeploymentID { value: id }
     }
 
-    fn bytes32(&self) -> String {
-        self.value.clone()
+    /// Bytes32 hex representation, as used by allocations in the network subgraph
+    pub fn bytes32(&self) -> Option<String> {
+        if self.value.starts_with("Qm") {
+            let bytes = bs58::decode(&self.value).into_vec().ok()?;
+            match bytes.as_slice() {
+                [0x12, 0x20, digest @ ..] if digest.len() == 32 => {
+                    Some(format!("0x{}", hex::encode(digest)))
+                }
+                _ => None,
+            }
+        } else {
+            Some(self.value.to_ascii_lowercase())
+        }
     }
 
-    fn ipfs_hash(&self) -> String {
-        let value = self.value.clone();
-        let mut bytes: Vec<u8> = vec![0x12, 0x20];
-        bytes.extend(value.as_bytes().to_vec());
-        let encoded = bytes.encode();
-        String::from_utf8(encoded).unwrap()
+    /// IPFS hash (`Qm...`) representation, as used by graph-node
+    pub fn ipfs_hash(&self) -> Option<String> {
+        match self.value.strip_prefix("0x") {
+            Some(digest) => {
+                let mut bytes: Vec<u8> = vec![0x12, 0x20];
+                bytes.extend(
+                    hex::decode(digest)
+                        .ok()
+                        .filter(|digest| digest.len() == 32)?,
+                );
+                Some(bs58::encode(bytes).into_string())
+            }
+            None => Some(self.value.clone()),
+        }
     }
 }
 
@@ -71,15 +95,22 @@ impl ToString for SubgraphDeploymentID {
         self.value.to_string()
     }
 }
-pub struct Signature {
-    v: i64,
-    r: String,
-    s: String,
+/// Result of a query, with an attestation if it was paid for and is attestable
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    pub graphql_response: String,
+    pub attestable: bool,
+    pub attestation: Option<Attestation>,
 }
 
-pub struct QueryResult {
-    graphql_response: String,
-    attestation: Option<Signature>,
+impl From<UnattestedQueryResult> for QueryResult {
+    fn from(result: UnattestedQueryResult) -> Self {
+        QueryResult {
+            graphql_response: result.graphql_response,
+            attestable: result.attestable,
+            attestation: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -103,6 +134,14 @@ pub struct FreeQuery {
     pub query: String,
 }
 
+/// Query paid for with a receipt for an allocation of the indexer on the deployment
+#[derive(Debug)]
+pub struct PaidQuery {
+    pub subgraph_deployment_id: SubgraphDeploymentID,
+    pub allocation_id: String,
+    pub query: String,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum QueryError {
     #[error(transparent)]
@@ -118,21 +157,22 @@ pub enum QueryError {
 #[derive(Debug, Clone)]
 pub struct QueryProcessor {
     client: Client,
-    base: Url,
     graph_node: GraphNodeInstance,
-    network_subgraph: Url,
+    network_subgraph: NetworkSubgraphClient,
+    cache: Option<Arc<QueryCache>>,
 }
 
 impl QueryProcessor {
-    pub fn new(graph_node_endpoint: &str, network_subgraph_endpoint: &str) -> QueryProcessor {
-        let graph_node = GraphNodeInstance::new(graph_node_endpoint);
-
+    pub fn new(
+        graph_node: GraphNodeInstance,
+        network_subgraph: NetworkSubgraphClient,
+        cache: Option<Arc<QueryCache>>,
+    ) -> QueryProcessor {
         QueryProcessor {
             client: Client::new(),
-            base: Url::parse(graph_node_endpoint).expect("Could not parse graph node endpoint"),
             graph_node,
-            network_subgraph: Url::parse(network_subgraph_endpoint)
-                .expect("Could not parse graph node endpoint"),
+            network_subgraph,
+            cache,
         }
     }
 
@@ -140,11 +180,65 @@ impl QueryProcessor {
         &self,
         query: FreeQuery,
     ) -> Result<Response<UnattestedQueryResult>, QueryError> {
+        self.execute_query(&query.subgraph_deployment_id, query.query)
+            .await
+    }
+
+    /// Execute a paid query, attesting to the response with the signer of the allocation
+    pub async fn execute_paid_query(
+        &self,
+        query: PaidQuery,
+        signer: &AttestationSigner,
+    ) -> Result<Response<QueryResult>, QueryError> {
+        trace!(allocation = %query.allocation_id, "Serving paid query");
         let response = self
-            .graph_node
-            .subgraph_query(&query.subgraph_deployment_id.value, query.query)
+            .execute_query(&query.subgraph_deployment_id, query.query.clone())
             .await?;
 
+        let result = response.result;
+        let attestation = result
+            .attestable
+            .then(|| signer.create_attestation(&query.query, &result.graphql_response));
+        Ok(Response {
+            result: QueryResult {
+                graphql_response: result.graphql_response,
+                attestable: result.attestable,
+                attestation,
+            },
+            status: response.status,
+        })
+    }
+
+    async fn execute_query(
+        &self,
+        subgraph_deployment_id: &SubgraphDeploymentID,
+        query: String,
+    ) -> Result<Response<UnattestedQueryResult>, QueryError> {
+        let deployment = &subgraph_deployment_id.value;
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| QueryCache::key(deployment, &query).map(|key| (cache, key)));
+
+        if let Some((cache, key)) = &cached {
+            if let Some(response) = cache.get(key) {
+                return Ok(Response {
+                    result: response,
+                    status: 200,
+                });
+            }
+        }
+
+        // Head observed before sending the query, so a response is never tied to an older block
+        let head = cached
+            .as_ref()
+            .and_then(|(cache, _)| cache.head(deployment));
+        let response = self.graph_node.subgraph_query(deployment, query).await?;
+
+        if let Some((cache, key)) = cached {
+            cache.insert(key, response.clone(), head);
+        }
+
         Ok(Response {
             result: response,
             status: 200,
@@ -155,10 +249,7 @@ impl QueryProcessor {
         &self,
         query: String,
     ) -> Result<Response<UnattestedQueryResult>, QueryError> {
-        let response = self
-            .graph_node
-            .network_query(self.network_subgraph.clone(), query)
-            .await?;
+        let response = self.network_subgraph.query(query).await?;
 
         Ok(Response {
             result: response,
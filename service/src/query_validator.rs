@@ -0,0 +1,354 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use graphql_parser::query::{Definition, OperationDefinition, Selection, SelectionSet, Value};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::config::ConfigError;
+
+/// Limits on the shape of a GraphQL query, unset limits are not enforced
+///
+/// Parsed from and displayed as `depth=16,fields=1000,aliases=50,first=1000,skip=5000`,
+/// where every limit is optional.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct QueryLimits {
+    /// Maximum nesting of fields
+    pub max_depth: Option<usize>,
+    /// Maximum number of fields, counting the fields of every fragment use
+    pub max_fields: Option<usize>,
+    /// Maximum number of aliased fields
+    pub max_aliases: Option<usize>,
+    /// Maximum value of `first` arguments
+    pub max_first: Option<u64>,
+    /// Maximum value of `skip` arguments
+    pub max_skip: Option<u64>,
+}
+
+impl FromStr for QueryLimits {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut limits = QueryLimits::default();
+        for limit in s
+            .split(',')
+            .map(str::trim)
+            .filter(|limit| !limit.is_empty())
+        {
+            let invalid = || {
+                ConfigError::ValidateInput(format!(
+                    "Invalid query limit `{}`, expected `name=value` with name among depth, fields, aliases, first and skip",
+                    limit
+                ))
+            };
+            let (name, value) = limit.split_once('=').ok_or_else(invalid)?;
+            let value: u64 = value.trim().parse().map_err(|_| invalid())?;
+            match name.trim() {
+                "depth" => limits.max_depth = Some(value as usize),
+                "fields" => limits.max_fields = Some(value as usize),
+                "aliases" => limits.max_aliases = Some(value as usize),
+                "first" => limits.max_first = Some(value),
+                "skip" => limits.max_skip = Some(value),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+impl fmt::Display for QueryLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limits: Vec<String> = [
+            ("depth", self.max_depth.map(|limit| limit as u64)),
+            ("fields", self.max_fields.map(|limit| limit as u64)),
+            ("aliases", self.max_aliases.map(|limit| limit as u64)),
+            ("first", self.max_first),
+            ("skip", self.max_skip),
+        ]
+        .iter()
+        .filter_map(|(name, limit)| limit.map(|limit| format!("{}={}", name, limit)))
+        .collect();
+        write!(f, "{}", limits.join(","))
+    }
+}
+
+impl TryFrom<String> for QueryLimits {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<QueryLimits> for String {
+    fn from(limits: QueryLimits) -> Self {
+        limits.to_string()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueryValidationError {
+    #[error("Invalid query request: {0}")]
+    InvalidRequest(String),
+    #[error("Query depth exceeds the limit of {0}")]
+    TooDeep(usize),
+    #[error("Query selects more than {0} fields")]
+    TooManyFields(usize),
+    #[error("Query uses more than {0} aliases")]
+    TooManyAliases(usize),
+    #[error("Argument `{argument}` of field `{field}` exceeds the limit of {limit}")]
+    ArgumentTooLarge {
+        field: String,
+        argument: String,
+        limit: u64,
+    },
+    #[error("Argument `{argument}` of field `{field}` must be an integer")]
+    NotAnInteger { field: String, argument: String },
+}
+
+impl QueryLimits {
+    /// Check a query request body `{ "query": ..., "variables": ... }` against the limits
+    pub fn validate(&self, body: &str) -> Result<(), QueryValidationError> {
+        if *self == QueryLimits::default() {
+            return Ok(());
+        }
+
+        let body: JsonValue = serde_json::from_str(body)
+            .map_err(|e| QueryValidationError::InvalidRequest(e.to_string()))?;
+        let query = body
+            .get("query")
+            .and_then(|query| query.as_str())
+            .ok_or_else(|| QueryValidationError::InvalidRequest("Missing query".to_string()))?;
+        let document = graphql_parser::parse_query::<&str>(query)
+            .map_err(|e| QueryValidationError::InvalidRequest(e.to_string()))?;
+
+        let mut walker = Walker {
+            limits: self,
+            variables: body.get("variables").unwrap_or(&JsonValue::Null),
+            defaults: HashMap::new(),
+            fragments: HashMap::new(),
+            fields: 0,
+            aliases: 0,
+        };
+        for definition in &document.definitions {
+            if let Definition::Fragment(fragment) = definition {
+                walker
+                    .fragments
+                    .insert(fragment.name, &fragment.selection_set);
+            }
+        }
+        for definition in &document.definitions {
+            let (selection_set, variable_definitions) = match definition {
+                Definition::Operation(OperationDefinition::SelectionSet(set)) => (set, &[][..]),
+                Definition::Operation(OperationDefinition::Query(query)) => {
+                    (&query.selection_set, &query.variable_definitions[..])
+                }
+                Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                    (&mutation.selection_set, &mutation.variable_definitions[..])
+                }
+                Definition::Operation(OperationDefinition::Subscription(subscription)) => (
+                    &subscription.selection_set,
+                    &subscription.variable_definitions[..],
+                ),
+                Definition::Fragment(_) => continue,
+            };
+            walker.defaults = variable_definitions
+                .iter()
+                .filter_map(|variable| Some((variable.name, variable.default_value.as_ref()?)))
+                .collect();
+            walker.walk(selection_set, 0, &mut Vec::new())?;
+        }
+        Ok(())
+    }
+}
+
+struct Walker<'a> {
+    limits: &'a QueryLimits,
+    variables: &'a JsonValue,
+    /// Default values of the variables declared by the operation being walked
+    defaults: HashMap<&'a str, &'a Value<'a, &'a str>>,
+    fragments: HashMap<&'a str, &'a SelectionSet<'a, &'a str>>,
+    fields: usize,
+    aliases: usize,
+}
+
+impl<'a> Walker<'a> {
+    fn walk(
+        &mut self,
+        selection_set: &'a SelectionSet<'a, &'a str>,
+        depth: usize,
+        spreads: &mut Vec<&'a str>,
+    ) -> Result<(), QueryValidationError> {
+        for selection in &selection_set.items {
+            match selection {
+                Selection::Field(field) => {
+                    let depth = depth + 1;
+                    check(self.limits.max_depth, depth, QueryValidationError::TooDeep)?;
+                    self.fields += 1;
+                    check(
+                        self.limits.max_fields,
+                        self.fields,
+                        QueryValidationError::TooManyFields,
+                    )?;
+                    if field.alias.is_some() {
+                        self.aliases += 1;
+                        check(
+                            self.limits.max_aliases,
+                            self.aliases,
+                            QueryValidationError::TooManyAliases,
+                        )?;
+                    }
+                    for (argument, value) in &field.arguments {
+                        let limit = match *argument {
+                            "first" => self.limits.max_first,
+                            "skip" => self.limits.max_skip,
+                            _ => None,
+                        };
+                        let limit = match limit {
+                            Some(limit) => limit,
+                            None => continue,
+                        };
+                        let value = self.integer(value).map_err(|_| {
+                            QueryValidationError::NotAnInteger {
+                                field: field.name.to_string(),
+                                argument: argument.to_string(),
+                            }
+                        })?;
+                        if let Some(value) = value {
+                            if value > limit {
+                                return Err(QueryValidationError::ArgumentTooLarge {
+                                    field: field.name.to_string(),
+                                    argument: argument.to_string(),
+                                    limit,
+                                });
+                            }
+                        }
+                    }
+                    self.walk(&field.selection_set, depth, spreads)?;
+                }
+                Selection::InlineFragment(fragment) => {
+                    self.walk(&fragment.selection_set, depth, spreads)?;
+                }
+                Selection::FragmentSpread(spread) => {
+                    // Cyclic fragments are left for graph-node to reject
+                    if spreads.contains(&spread.fragment_name) {
+                        continue;
+                    }
+                    if let Some(fragment) = self.fragments.get(spread.fragment_name).copied() {
+                        spreads.push(spread.fragment_name);
+                        self.walk(fragment, depth, spreads)?;
+                        spreads.pop();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of an integer argument, given inline or through a variable, falling back to
+    /// the default value of the variable. `None` if unset, and an error if not an integer.
+    fn integer(&self, value: &Value<'a, &'a str>) -> Result<Option<u64>, ()> {
+        match value {
+            Value::Int(number) => number
+                .as_i64()
+                .map(|number| Some(number.max(0) as u64))
+                .ok_or(()),
+            Value::Variable(name) => match self.variables.get(*name) {
+                Some(JsonValue::Null) | None => match self.defaults.get(*name) {
+                    Some(default) => self.integer(default),
+                    None => Ok(None),
+                },
+                Some(JsonValue::Number(number)) => number
+                    .as_u64()
+                    .or_else(|| number.as_i64().map(|_| 0))
+                    .map(Some)
+                    .ok_or(()),
+                Some(_) => Err(()),
+            },
+            Value::Null => Ok(None),
+            _ => Err(()),
+        }
+    }
+}
+
+fn check(
+    limit: Option<usize>,
+    value: usize,
+    error: fn(usize) -> QueryValidationError,
+) -> Result<(), QueryValidationError> {
+    match limit {
+        Some(limit) if value > limit => Err(error(limit)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn limits() -> QueryLimits {
+        "first=100,skip=1000".parse().unwrap()
+    }
+
+    fn validate(query: &str, variables: JsonValue) -> Result<(), QueryValidationError> {
+        limits().validate(&json!({ "query": query, "variables": variables }).to_string())
+    }
+
+    #[test]
+    fn variables_fall_back_to_their_default_value() {
+        let query = "query tokens($first: Int = 500) { tokens(first: $first) { id } }";
+        assert!(matches!(
+            validate(query, json!({})),
+            Err(QueryValidationError::ArgumentTooLarge { limit: 100, .. })
+        ));
+        assert!(matches!(
+            validate(query, json!({ "first": null })),
+            Err(QueryValidationError::ArgumentTooLarge { limit: 100, .. })
+        ));
+        assert!(validate(query, json!({ "first": 50 })).is_ok());
+
+        let query = "query tokens($skip: Int = 10) { tokens(skip: $skip) { id } }";
+        assert!(validate(query, json!(null)).is_ok());
+        assert!(matches!(
+            validate(query, json!({ "skip": 5000 })),
+            Err(QueryValidationError::ArgumentTooLarge { limit: 1000, .. })
+        ));
+    }
+
+    #[test]
+    fn limited_arguments_must_be_integers() {
+        for query in [
+            "{ tokens(first: 10.5) { id } }",
+            "{ tokens(skip: \"10\") { id } }",
+            "query tokens($first: Float = 500.5) { tokens(first: $first) { id } }",
+        ] {
+            assert!(
+                matches!(
+                    validate(query, json!({})),
+                    Err(QueryValidationError::NotAnInteger { .. })
+                ),
+                "{}",
+                query
+            );
+        }
+        for variables in [json!({ "first": 10.5 }), json!({ "first": "10" })] {
+            assert!(matches!(
+                validate(
+                    "query tokens($first: Int) { tokens(first: $first) { id } }",
+                    variables
+                ),
+                Err(QueryValidationError::NotAnInteger { .. })
+            ));
+        }
+
+        // Other arguments are left to graph-node
+        assert!(validate(
+            "{ tokens(where: { name: \"a\" }, orderBy: name) { id } }",
+            json!({})
+        )
+        .is_ok());
+        assert!(validate("{ tokens(first: 100, skip: -1) { id } }", json!({})).is_ok());
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use diesel::{sql_query, RunQueryDsl};
+use ethers::providers::{Http, Middleware, Provider};
+use reqwest::{header, Client};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::common::database::PgPool;
+
+/// External services the indexer service needs to be able to serve queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Dependency {
+    GraphNodeQuery,
+    GraphNodeStatus,
+    Postgres,
+    NetworkSubgraph,
+    Ethereum,
+    /// Chain ID and contract addresses, resolved at startup rather than probed
+    Chain,
+}
+
+/// Outcome of the latest probe against a dependency
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyStatus {
+    pub healthy: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl DependencyStatus {
+    fn unchecked() -> Self {
+        DependencyStatus {
+            healthy: false,
+            latency_ms: None,
+            error: Some("Not checked yet".to_string()),
+        }
+    }
+}
+
+/// Readiness report served at `/ready`
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub dependencies: BTreeMap<Dependency, DependencyStatus>,
+}
+
+/// Endpoints probed to decide whether the service is ready
+#[derive(Debug, Clone)]
+pub struct ProbeTargets {
+    pub graph_node_query_endpoints: Vec<String>,
+    pub graph_node_status_endpoint: String,
+    pub postgres: PgPool,
+    pub network_subgraph_endpoint: String,
+    pub ethereum: String,
+}
+
+/// Tracks the state of the service dependencies, refreshed by periodic probes
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    client: Client,
+    targets: Arc<ProbeTargets>,
+    statuses: Arc<RwLock<BTreeMap<Dependency, DependencyStatus>>>,
+}
+
+impl Readiness {
+    pub fn new(targets: ProbeTargets) -> Readiness {
+        let client = reqwest::Client::builder()
+            .user_agent("indexer-service")
+            .timeout(Duration::from_secs(5))
+            .build()
+            .expect("Could not build a client for dependency probes");
+        let statuses = [
+            Dependency::GraphNodeQuery,
+            Dependency::GraphNodeStatus,
+            Dependency::Postgres,
+            Dependency::NetworkSubgraph,
+            Dependency::Ethereum,
+            Dependency::Chain,
+        ]
+        .into_iter()
+        .map(|dependency| (dependency, DependencyStatus::unchecked()))
+        .collect();
+
+        Readiness {
+            client,
+            targets: Arc::new(targets),
+            statuses: Arc::new(RwLock::new(statuses)),
+        }
+    }
+
+    /// Probe every dependency once and record the results
+    pub async fn check_all(&self) {
+        let (graph_node_query, graph_node_status, postgres, network_subgraph, ethereum) = tokio::join!(
+            timed(self.probe_graph_node_query()),
+            timed(self.probe_graph_node_status()),
+            timed(self.probe_postgres()),
+            timed(self.probe_network_subgraph()),
+            timed(self.probe_ethereum()),
+        );
+
+        let mut statuses = self.statuses.write().await;
+        for (dependency, status) in [
+            (Dependency::GraphNodeQuery, graph_node_query),
+            (Dependency::GraphNodeStatus, graph_node_status),
+            (Dependency::Postgres, postgres),
+            (Dependency::NetworkSubgraph, network_subgraph),
+            (Dependency::Ethereum, ethereum),
+        ] {
+            match &status.error {
+                Some(error) => warn!(?dependency, %error, "Dependency probe failed"),
+                None => debug!(
+                    ?dependency,
+                    latency_ms = status.latency_ms,
+                    "Dependency probe succeeded"
+                ),
+            }
+            statuses.insert(dependency, status);
+        }
+    }
+
+    /// Record the state of a dependency that is not probed periodically
+    pub async fn record(&self, dependency: Dependency, result: Result<(), String>) {
+        let status = DependencyStatus {
+            healthy: result.is_ok(),
+            latency_ms: None,
+            error: result.err(),
+        };
+        self.statuses.write().await.insert(dependency, status);
+    }
+
+    /// Keep probing the dependencies at the given interval
+    pub async fn monitor(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.check_all().await;
+        }
+    }
+
+    pub async fn report(&self) -> ReadinessReport {
+        let dependencies = self.statuses.read().await.clone();
+        ReadinessReport {
+            ready: dependencies.values().all(|status| status.healthy),
+            dependencies,
+        }
+    }
+
+    /// Startup check, logs a summary of the dependency states
+    pub async fn log_startup_check(&self) {
+        self.check_all().await;
+        let report = self.report().await;
+        if report.ready {
+            info!("All dependencies are reachable");
+        } else {
+            let unhealthy: Vec<Dependency> = report
+                .dependencies
+                .iter()
+                .filter(|(_, status)| !status.healthy)
+                .map(|(dependency, _)| *dependency)
+                .collect();
+            warn!(
+                ?unhealthy,
+                "Service is not ready to serve, waiting for dependencies"
+            );
+        }
+    }
+
+    /// Queries can be served as long as one of the graph node query endpoints is reachable
+    async fn probe_graph_node_query(&self) -> Result<(), String> {
+        let mut errors = vec![];
+        for endpoint in &self.targets.graph_node_query_endpoints {
+            match self
+                .client
+                .get(endpoint)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+            {
+                Ok(_) => return Ok(()),
+                Err(e) => errors.push(format!("{}: {}", endpoint, e)),
+            }
+        }
+        Err(errors.join(", "))
+    }
+
+    async fn probe_graph_node_status(&self) -> Result<(), String> {
+        self.graphql_probe(&self.targets.graph_node_status_endpoint, "{ __typename }")
+            .await
+    }
+
+    async fn probe_network_subgraph(&self) -> Result<(), String> {
+        self.graphql_probe(
+            &self.targets.network_subgraph_endpoint,
+            "{ _meta { block { number } } }",
+        )
+        .await
+    }
+
+    async fn probe_postgres(&self) -> Result<(), String> {
+        let pool = self.targets.postgres.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = pool
+                .get_timeout(Duration::from_secs(5))
+                .map_err(|e| e.to_string())?;
+            sql_query("SELECT 1")
+                .execute(&mut connection)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    async fn probe_ethereum(&self) -> Result<(), String> {
+        let provider = Provider::<Http>::try_from(self.targets.ethereum.as_str())
+            .map_err(|e| e.to_string())?;
+        provider
+            .get_block_number()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Send a GraphQL query and require a response without errors
+    async fn graphql_probe(&self, endpoint: &str, query: &str) -> Result<(), String> {
+        let response_text = self
+            .client
+            .post(endpoint)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(serde_json::json!({ "query": query }).to_string())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+        let response: Value = serde_json::from_str(&response_text).map_err(|e| e.to_string())?;
+
+        match response.get("errors") {
+            Some(errors) => Err(errors.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+async fn timed<F>(probe: F) -> DependencyStatus
+where
+    F: std::future::Future<Output = Result<(), String>>,
+{
+    let start = Instant::now();
+    let result = probe.await;
+    let latency_ms = Some(start.elapsed().as_millis() as u64);
+    match result {
+        Ok(()) => DependencyStatus {
+            healthy: true,
+            latency_ms,
+            error: None,
+        },
+        Err(error) => DependencyStatus {
+            healthy: false,
+            latency_ms,
+            error: Some(error),
+        },
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    fmt,
+    str::FromStr,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequest, RequestParts},
+    http::{header, HeaderMap},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{config::ConfigError, metrics::AUTHENTICATED_REQUESTS, server::ServerOptions};
+
+/// What an auth token gives access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    /// Query subgraphs without a receipt
+    FreeQuery,
+    /// Query the network subgraph at `/network`
+    Network,
+    /// Query the indexing statuses at `/status`
+    Status,
+    /// Everything, including the administrative endpoints
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "free-query" => Ok(Scope::FreeQuery),
+            "network" => Ok(Scope::Network),
+            "status" => Ok(Scope::Status),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(ConfigError::ValidateInput(format!(
+                "Unknown auth token scope `{}`, expected free-query, network, status or admin",
+                s
+            ))),
+        }
+    }
+}
+
+/// Named bearer token granting a set of scopes. Only the SHA-256 digest of the token is kept.
+#[derive(Clone)]
+pub struct AuthToken {
+    pub name: String,
+    digest: [u8; 32],
+    pub scopes: HashSet<Scope>,
+}
+
+impl fmt::Debug for AuthToken {
+    // Keep the token itself out of logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthToken")
+            .field("name", &self.name)
+            .field("scopes", &self.scopes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuthToken {
+    pub fn new(name: &str, token: &str, scopes: impl IntoIterator<Item = Scope>) -> Self {
+        AuthToken {
+            name: name.to_string(),
+            digest: digest(token),
+            scopes: scopes.into_iter().collect(),
+        }
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin)
+    }
+}
+
+impl FromStr for AuthToken {
+    type Err = ConfigError;
+
+    /// Parse `name:scope[+scope...]:token`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some(scopes), Some(token)) if !name.is_empty() && !token.is_empty() => {
+                let scopes = scopes
+                    .split('+')
+                    .map(Scope::from_str)
+                    .collect::<Result<HashSet<Scope>, ConfigError>>()?;
+                Ok(AuthToken::new(name, token, scopes))
+            }
+            _ => Err(ConfigError::ValidateInput(
+                "Auth tokens must be formatted as `name:scope[+scope...]:token`".to_string(),
+            )),
+        }
+    }
+}
+
+fn digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+/// Tokens accepted by the server, by digest
+#[derive(Debug, Clone, Default)]
+pub struct AuthTokens(Arc<HashMap<[u8; 32], AuthToken>>);
+
+impl AuthTokens {
+    pub fn new(tokens: Vec<AuthToken>) -> Self {
+        AuthTokens(Arc::new(
+            tokens
+                .into_iter()
+                .map(|token| (token.digest, token))
+                .collect(),
+        ))
+    }
+
+    /// Parse a file of tokens, one `name:scope[+scope...]:token` per line. Blank lines and
+    /// lines starting with `#` are skipped.
+    pub fn parse_file(contents: &str) -> Result<Vec<AuthToken>, ConfigError> {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(AuthToken::from_str)
+            .collect()
+    }
+
+    /// Token presented in the `Authorization` header, with or without the `Bearer` scheme
+    fn presented(headers: &HeaderMap) -> Option<&str> {
+        let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?.trim();
+        match value.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => Some(token.trim()),
+            _ => Some(value),
+        }
+    }
+
+    /// Find the token matching the presented one. Digests are compared rather than the tokens,
+    /// so the response time does not tell how much of a token matched.
+    pub fn authenticate(&self, presented: &str) -> Option<&AuthToken> {
+        self.0.get(&digest(presented))
+    }
+}
+
+/// Extractor for the auth token a request was made with, if any
+#[derive(Debug, Clone)]
+pub struct Authenticated(pub Option<AuthToken>);
+
+impl Authenticated {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.0.as_ref().is_some_and(|token| token.has_scope(scope))
+    }
+
+    /// Name of the token for logs and metrics
+    pub fn token_name(&self) -> &str {
+        self.0.as_ref().map_or("none", |token| token.name.as_str())
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Authenticated {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let presented = match AuthTokens::presented(req.headers()) {
+            Some(presented) => presented,
+            None => return Ok(Authenticated(None)),
+        };
+        let token = req
+            .extensions()
+            .get::<ServerOptions>()
+            .and_then(|server| server.auth_tokens.authenticate(presented))
+            .cloned();
+
+        let name = token
+            .as_ref()
+            .map_or("invalid", |token| token.name.as_str());
+        debug!(token = name, path = %req.uri().path(), "Authenticated request");
+        AUTHENTICATED_REQUESTS.with_label_values(&[name]).inc();
+        Ok(Authenticated(token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_authenticated_by_digest() {
+        let tokens = AuthTokens::parse_file(
+            "# operators\nalice:status+network:alice-token\n\nbob:admin:bob:token\n",
+        );
+        let tokens = AuthTokens::new(tokens.unwrap());
+
+        let alice = tokens.authenticate("alice-token").unwrap();
+        assert_eq!(alice.name, "alice");
+        assert!(alice.has_scope(Scope::Status) && !alice.has_scope(Scope::Admin));
+        assert_eq!(tokens.authenticate("bob:token").unwrap().name, "bob");
+        assert!(tokens.authenticate("alice-toke").is_none());
+        assert!(!format!("{:?}", tokens).contains("alice-token"));
+    }
+
+    #[test]
+    fn malformed_token_lines_are_rejected() {
+        assert!(AuthTokens::parse_file("alice:status").is_err());
+        assert!(AuthTokens::parse_file("alice:owner:alice-token").is_err());
+    }
+}
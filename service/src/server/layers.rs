@@ -0,0 +1,216 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::Extension,
+    http::{header, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+    Router,
+};
+use futures::stream;
+use http_body::{Body as _, Limited};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use tower::ServiceExt;
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+use crate::{
+    config::{ConfigError, ServerSettings},
+    server::routes::graphql_error_response_with_status,
+};
+
+/// Request timeouts by route, matched on the longest path prefix
+///
+/// Parsed from and displayed as `*=10000,/subgraphs=30000,/status=5000`, in milliseconds,
+/// where `*` applies to the routes without a prefix of their own. Routes matching no
+/// entry have no timeout.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RouteTimeouts {
+    default: Option<Duration>,
+    routes: Vec<(String, Duration)>,
+}
+
+impl RouteTimeouts {
+    /// Timeout of the route serving `path`
+    pub fn timeout(&self, path: &str) -> Option<Duration> {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, timeout)| *timeout)
+            .or(self.default)
+    }
+
+    /// Middleware answering `504` with a GraphQL error once the route timeout elapses
+    pub async fn enforce(self, req: Request<Body>, next: Next<Body>) -> Response {
+        let timeout = match self.timeout(req.uri().path()) {
+            Some(timeout) => timeout,
+            None => return next.run(req).await,
+        };
+        match tokio::time::timeout(timeout, next.run(req)).await {
+            Ok(response) => response,
+            Err(_) => graphql_error_response_with_status(
+                StatusCode::GATEWAY_TIMEOUT,
+                &format!("Request timed out after {} ms", timeout.as_millis()),
+            ),
+        }
+    }
+}
+
+impl FromStr for RouteTimeouts {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut timeouts = RouteTimeouts::default();
+        for timeout in s
+            .split(',')
+            .map(str::trim)
+            .filter(|timeout| !timeout.is_empty())
+        {
+            let invalid = || {
+                ConfigError::ValidateInput(format!(
+                    "Invalid route timeout `{}`, expected `/path-prefix=milliseconds` or `*=milliseconds`",
+                    timeout
+                ))
+            };
+            let (route, millis) = timeout.split_once('=').ok_or_else(invalid)?;
+            let millis: u64 = millis.trim().parse().map_err(|_| invalid())?;
+            match route.trim() {
+                "*" => timeouts.default = Some(Duration::from_millis(millis)),
+                route if route.starts_with('/') => timeouts
+                    .routes
+                    .push((route.to_string(), Duration::from_millis(millis))),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(timeouts)
+    }
+}
+
+impl fmt::Display for RouteTimeouts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let timeouts: Vec<String> = self
+            .default
+            .iter()
+            .map(|timeout| ("*", timeout))
+            .chain(
+                self.routes
+                    .iter()
+                    .map(|(route, timeout)| (route.as_str(), timeout)),
+            )
+            .map(|(route, timeout)| format!("{}={}", route, timeout.as_millis()))
+            .collect();
+        write!(f, "{}", timeouts.join(","))
+    }
+}
+
+impl TryFrom<String> for RouteTimeouts {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<RouteTimeouts> for String {
+    fn from(timeouts: RouteTimeouts) -> Self {
+        timeouts.to_string()
+    }
+}
+
+/// CORS policy from the allowed origins and headers, `*` allowing any
+pub fn cors_layer(settings: &ServerSettings) -> Result<CorsLayer, ConfigError> {
+    let origins = if settings.cors_allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            settings
+                .cors_allowed_origins
+                .iter()
+                .map(|origin| {
+                    HeaderValue::from_str(origin).map_err(|_| {
+                        ConfigError::ValidateInput(format!("Invalid CORS origin `{}`", origin))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        )
+    };
+    let headers = if settings.cors_allowed_headers.iter().any(|h| h == "*") {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(
+            settings
+                .cors_allowed_headers
+                .iter()
+                .map(|name| {
+                    HeaderName::from_str(name).map_err(|_| {
+                        ConfigError::ValidateInput(format!("Invalid CORS header `{}`", name))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        )
+    };
+    Ok(CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST])
+        .allow_origin(origins)
+        .allow_headers(headers)
+        .expose_headers([HeaderName::from_static("graph-attestable")]))
+}
+
+/// Middleware rejecting request bodies larger than `limit` bytes, from the announced
+/// `Content-Length` or else while the body is read
+pub async fn limit_body(limit: usize, req: Request<Body>, next: Next<Body>) -> Response {
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > limit) {
+        return graphql_error_response_with_status(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!("Request body exceeds the limit of {} bytes", limit),
+        );
+    }
+    let req = req.map(|body| {
+        Body::wrap_stream(stream::unfold(
+            Limited::new(body, limit),
+            |mut body| async move { body.data().await.map(|chunk| (chunk, body)) },
+        ))
+    });
+    next.run(req).await
+}
+
+/// Routes that need the service to be started up, answering `503` until they are opened
+#[derive(Debug, Clone, Default)]
+pub struct StartupGate(Arc<OnceCell<Mutex<Router>>>);
+
+impl StartupGate {
+    /// Serve the routes from now on
+    pub fn open(&self, routes: Router) {
+        // Opened once, at the end of the startup
+        let _ = self.0.set(Mutex::new(routes));
+    }
+
+    /// Fallback handler forwarding requests to the routes once opened
+    pub async fn forward(Extension(gate): Extension<StartupGate>, req: Request<Body>) -> Response {
+        // Routers are not `Sync`, each request is served by a clone
+        let routes = gate.0.get().map(|routes| routes.lock().unwrap().clone());
+        match routes {
+            Some(routes) => routes
+                .oneshot(req)
+                .await
+                .unwrap_or_else(|infallible| match infallible {}),
+            None => graphql_error_response_with_status(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The service is starting up",
+            ),
+        }
+    }
+}
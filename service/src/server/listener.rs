@@ -0,0 +1,295 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    body::Body,
+    http::{HeaderName, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use axum_server::{
+    accept::{Accept, DefaultAcceptor},
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+    AddrIncomingConfig, Handle, HttpConfig,
+};
+use futures::future::BoxFuture;
+use rustls::{
+    server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth},
+    Certificate, PrivateKey, RootCertStore, ServerConfig,
+};
+use rustls_pemfile::Item;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower_http::add_extension::AddExtension;
+use tracing::{info, warn};
+
+use crate::config::ServerSettings;
+
+/// Routes of the query server that need a verified client certificate when a client CA
+/// is configured
+pub const CLIENT_AUTH_ROUTES: [&str; 2] = ["/status", "/network"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("Failed to read {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("No certificate found in {0}")]
+    NoCertificate(PathBuf),
+    #[error("No private key found in {0}")]
+    NoPrivateKey(PathBuf),
+    #[error("Invalid client CA certificate in {0}: {1}")]
+    ClientCa(PathBuf, String),
+    #[error("Invalid TLS configuration: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+/// Whether connections must present a client certificate signed by the client CA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Verified when presented, routes requiring one check for it
+    Optional,
+    /// Connections without a verified certificate are refused at the handshake
+    Required,
+}
+
+/// PEM files of a TLS listener, with the CA verifying client certificates if set
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+    pub client_auth: ClientAuth,
+}
+
+impl TlsFiles {
+    fn server_config(&self) -> Result<Arc<ServerConfig>, TlsError> {
+        let certs: Vec<Certificate> = read_pem(&self.cert)?
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::X509Certificate(cert) => Some(Certificate(cert)),
+                _ => None,
+            })
+            .collect();
+        if certs.is_empty() {
+            return Err(TlsError::NoCertificate(self.cert.clone()));
+        }
+        let key = read_pem(&self.key)?
+            .into_iter()
+            .find_map(|item| match item {
+                Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| TlsError::NoPrivateKey(self.key.clone()))?;
+
+        let verifier = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for item in read_pem(client_ca)? {
+                    if let Item::X509Certificate(cert) = item {
+                        roots
+                            .add(&Certificate(cert))
+                            .map_err(|e| TlsError::ClientCa(client_ca.clone(), e.to_string()))?;
+                    }
+                }
+                match self.client_auth {
+                    ClientAuth::Optional => {
+                        AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()
+                    }
+                    ClientAuth::Required => AllowAnyAuthenticatedClient::new(roots).boxed(),
+                }
+            }
+            None => NoClientAuth::boxed(),
+        };
+
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certs, key)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    /// Last modification of the files, to notice renewed certificates
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [Some(&self.cert), Some(&self.key), self.client_ca.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<Item>, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Read(path.to_path_buf(), e))?;
+    let mut reader = BufReader::new(file);
+    let mut items = vec![];
+    while let Some(item) =
+        rustls_pemfile::read_one(&mut reader).map_err(|e| TlsError::Read(path.to_path_buf(), e))?
+    {
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// TLS termination of a listener, reloading its certificates when the files change
+#[derive(Debug, Clone)]
+pub struct TlsListener {
+    files: TlsFiles,
+    config: RustlsConfig,
+}
+
+impl TlsListener {
+    pub fn new(files: TlsFiles) -> Result<Self, TlsError> {
+        let config = RustlsConfig::from_config(files.server_config()?);
+        Ok(TlsListener { files, config })
+    }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor {
+            inner: RustlsAcceptor::new(self.config.clone()),
+        }
+    }
+
+    /// Whether requests may carry a verified client certificate
+    pub fn verifies_clients(&self) -> bool {
+        self.files.client_ca.is_some()
+    }
+
+    /// Poll the certificate files and reload them once they change, keeping the current
+    /// certificates if the new ones are invalid
+    pub async fn watch(self, interval: Duration) {
+        let mut modified = self.files.modified();
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            let current = self.files.modified();
+            if current == modified {
+                continue;
+            }
+            modified = current;
+            match self.files.server_config() {
+                Ok(config) => {
+                    self.config.reload_from_config(config);
+                    info!(cert = %self.files.cert.display(), "Reloaded TLS certificates");
+                }
+                Err(e) => warn!(error = %e, "Failed to reload TLS certificates"),
+            }
+        }
+    }
+}
+
+/// Certificate state of the connection a request came from
+#[derive(Debug, Clone, Copy)]
+pub struct ClientCertificate {
+    pub verified: bool,
+}
+
+/// TLS acceptor recording in each request whether the client presented a verified
+/// certificate
+#[derive(Debug, Clone)]
+pub struct TlsAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl<I, S> Accept<I, S> for TlsAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, ClientCertificate>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let accept = self.inner.accept(stream, service);
+        Box::pin(async move {
+            let (stream, service) = accept.await?;
+            // Certificates are only kept after the verifier accepted them
+            let verified = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .is_some_and(|certs| !certs.is_empty());
+            Ok((
+                stream,
+                AddExtension::new(service, ClientCertificate { verified }),
+            ))
+        })
+    }
+}
+
+/// Middleware refusing requests to the client authenticated routes without a verified
+/// client certificate
+pub async fn require_client_certificate(req: Request<Body>, next: Next<Body>) -> Response {
+    let path = req.uri().path();
+    let protected = CLIENT_AUTH_ROUTES
+        .iter()
+        .any(|route| path == *route || path.starts_with(&format!("{}/", route)));
+    let verified = req
+        .extensions()
+        .get::<ClientCertificate>()
+        .is_some_and(|certificate| certificate.verified);
+    if protected && !verified {
+        return (
+            StatusCode::FORBIDDEN,
+            axum::response::AppendHeaders([(HeaderName::from_static("graph-attestable"), "false")]),
+            Json("A verified client certificate is required"),
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+/// HTTP/1 and HTTP/2 options of the query server
+pub fn http_config(settings: &ServerSettings) -> HttpConfig {
+    HttpConfig::new()
+        .http1_keep_alive(settings.http1_keepalive)
+        .http2_only(settings.http2_only)
+        .http2_keep_alive_interval(settings.http2_keepalive_interval.map(Duration::from_millis))
+        .http2_max_concurrent_streams(settings.http2_max_concurrent_streams)
+        .build()
+}
+
+/// TCP options of the query server
+pub fn addr_incoming_config(settings: &ServerSettings) -> AddrIncomingConfig {
+    AddrIncomingConfig::new()
+        .tcp_keepalive(settings.tcp_keepalive.map(Duration::from_millis))
+        .build()
+}
+
+/// Serve the app at `addr`, over TLS if it is configured, until the handle shuts it down
+pub async fn serve(
+    app: Router,
+    addr: SocketAddr,
+    tls: Option<TlsListener>,
+    http_config: HttpConfig,
+    addr_incoming_config: AddrIncomingConfig,
+    handle: Handle,
+) -> io::Result<()> {
+    let server = axum_server::bind(addr)
+        .http_config(http_config)
+        .addr_incoming_config(addr_incoming_config)
+        .handle(handle);
+    match tls {
+        Some(tls) => {
+            server
+                .acceptor(tls.acceptor())
+                .serve(app.into_make_service())
+                .await
+        }
+        None => {
+            server
+                .acceptor(DefaultAcceptor)
+                .serve(app.into_make_service())
+                .await
+        }
+    }
+}
@@ -1,19 +1,55 @@
-use crate::{query_processor::QueryProcessor, util::PackageVersion};
+use std::sync::Arc;
 
+use ethers::providers::{Http, Provider};
+use native::{attestation::AttestationDomain, signature_verification::SignatureVerifier};
+use tokio::sync::Mutex;
+
+use crate::{
+    allocation_monitor::AllocationMonitor,
+    common::{attestation::AttestationSigners, signer::OperatorSigner},
+    ethereum::EthereumClient,
+    indexer_management::IndexerManagementClient,
+    logging::LogHandle,
+    query_fee::allocations::AllocationReceiptManager,
+    query_processor::QueryProcessor,
+    query_validator::QueryLimits,
+    server::{auth::AuthTokens, subscriptions::SubscriptionConnections},
+    util::PackageVersion,
+};
+
+pub mod auth;
+pub mod layers;
+pub mod listener;
 pub mod routes;
+pub mod subscriptions;
 
 #[derive(Debug, Clone)]
 pub struct ServerOptions {
     pub port: Option<u32>,
     pub release: PackageVersion,
     pub query_processor: QueryProcessor,
-    pub free_query_auth_token: Option<String>,
+    pub auth_tokens: AuthTokens,
+    pub require_status_auth: bool,
+    pub free_query_limits: QueryLimits,
+    pub network_query_limits: QueryLimits,
     pub graph_node_status_endpoint: String,
-    // pub indexer_management_client: IndexerManagementClient,
-    pub operator_public_key: String,
+    pub indexer_management_client: IndexerManagementClient,
+    pub operator_signer: Arc<dyn OperatorSigner>,
     // pub network_subgraph: NetworkSubgraph,
-    pub network_subgraph_auth_token: Option<String>,
     pub serve_network_subgraph: bool,
+    pub attestation_domain: AttestationDomain,
+    pub ethereum: Arc<EthereumClient<Provider<Http>>>,
+    pub allocation_monitor: AllocationMonitor,
+    pub logging: LogHandle,
+    pub graph_node_ws_endpoint: Option<String>,
+    pub subscription_connections: SubscriptionConnections,
+    pub receipt_manager: Arc<Mutex<AllocationReceiptManager>>,
+    /// Verifier of the receipt signatures, shared with the receipt manager but not behind its
+    /// lock
+    pub receipt_verifier: Arc<SignatureVerifier>,
+    pub attestation_signers: AttestationSigners,
+    /// Client of the requests to graph-node made while serving queries
+    pub http_client: reqwest::Client,
 }
 
 impl ServerOptions {
@@ -22,21 +58,47 @@ impl ServerOptions {
         port: Option<u32>,
         release: PackageVersion,
         query_processor: QueryProcessor,
-        free_query_auth_token: Option<String>,
+        auth_tokens: AuthTokens,
+        require_status_auth: bool,
+        free_query_limits: QueryLimits,
+        network_query_limits: QueryLimits,
         graph_node_status_endpoint: String,
-        operator_public_key: String,
-        network_subgraph_auth_token: Option<String>,
+        operator_signer: Arc<dyn OperatorSigner>,
         serve_network_subgraph: bool,
+        attestation_domain: AttestationDomain,
+        ethereum: Arc<EthereumClient<Provider<Http>>>,
+        allocation_monitor: AllocationMonitor,
+        indexer_management_client: IndexerManagementClient,
+        logging: LogHandle,
+        graph_node_ws_endpoint: Option<String>,
+        subscription_connections: SubscriptionConnections,
+        receipt_manager: AllocationReceiptManager,
     ) -> Self {
+        let attestation_signers =
+            AttestationSigners::new(operator_signer.clone(), attestation_domain.clone());
+        let receipt_verifier = receipt_manager.verifier();
         ServerOptions {
             port,
             release,
             query_processor,
-            free_query_auth_token,
+            auth_tokens,
+            require_status_auth,
+            free_query_limits,
+            network_query_limits,
             graph_node_status_endpoint,
-            operator_public_key,
-            network_subgraph_auth_token,
+            operator_signer,
             serve_network_subgraph,
+            attestation_domain,
+            ethereum,
+            allocation_monitor,
+            indexer_management_client,
+            logging,
+            graph_node_ws_endpoint,
+            subscription_connections,
+            receipt_manager: Arc::new(Mutex::new(receipt_manager)),
+            receipt_verifier,
+            attestation_signers,
+            http_client: reqwest::Client::new(),
         }
     }
 }
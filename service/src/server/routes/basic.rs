@@ -3,19 +3,31 @@ use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde::Serialize;
 use serde_json::json;
 
-use crate::server::ServerOptions;
+use crate::{common::indexer_error::IndexerErrorCode, readiness::Readiness, server::ServerOptions};
 
 #[derive(Serialize)]
 struct Health {
     healthy: bool,
 }
 
-/// Endpoint for server health
+/// Endpoint for server liveness
 pub async fn health() -> impl IntoResponse {
     let health = Health { healthy: true };
     (StatusCode::OK, Json(health))
 }
 
+/// Endpoint for server readiness, only OK once every dependency is reachable and the
+/// chain is resolved
+pub async fn ready(Extension(readiness): Extension<Readiness>) -> impl IntoResponse {
+    let report = readiness.report().await;
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
 /// Index endpoint for status checks
 pub async fn index() -> impl IntoResponse {
     let responder = "Ready to roll!".to_string();
@@ -28,10 +40,21 @@ pub async fn version(server: axum::extract::Extension<ServerOptions>) -> impl In
     (StatusCode::OK, Json(version))
 }
 
+/// Endpoint listing the catalog of indexer error codes
+pub async fn errors() -> impl IntoResponse {
+    (StatusCode::OK, Json(IndexerErrorCode::catalog()))
+}
+
 // Define a handler function for the `/info` route
 async fn operator_info(Extension(options): Extension<ServerOptions>) -> Json<serde_json::Value> {
-    let public_key = &options.operator_public_key;
-    Json(json!({ "publicKey": public_key }))
+    let public_key = format!("{:?}", options.operator_signer.address());
+    let indexer_address = format!("{:?}", options.ethereum.indexer());
+    Json(json!({
+        "publicKey": public_key,
+        "indexerAddress": indexer_address,
+        // `null` until the authorization could be checked
+        "operatorAuthorized": options.ethereum.state().operator_authorized,
+    }))
 }
 
 // Create a function to build the operator server router
@@ -0,0 +1,11 @@
+use axum::{extract::Extension, response::IntoResponse, Json};
+
+use crate::model::ServiceSchema;
+
+/// Cost models of the indexer, served through the GraphQL schema
+pub async fn cost_queries(
+    Extension(schema): Extension<ServiceSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> impl IntoResponse {
+    Json(schema.execute(request).await)
+}
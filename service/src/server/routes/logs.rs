@@ -0,0 +1,93 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::info;
+
+use crate::server::{
+    auth::{Authenticated, Scope},
+    ServerOptions,
+};
+
+use super::bad_request_response;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogFilter {
+    filter: String,
+}
+
+/// Endpoint for the current log filter directives
+pub async fn log_filter(
+    Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
+) -> Response {
+    if !auth.has_scope(Scope::Admin) {
+        return bad_request_response("Not authorized to read the log filter");
+    }
+    let filter = LogFilter {
+        filter: server.logging.filter(),
+    };
+    (StatusCode::OK, Json(filter)).into_response()
+}
+
+/// Endpoint to replace the log filter directives without a restart
+pub async fn set_log_filter(
+    Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
+    Json(filter): Json<LogFilter>,
+) -> Response {
+    if !auth.has_scope(Scope::Admin) {
+        return bad_request_response("Not authorized to change the log filter");
+    }
+    if let Err(e) = server.logging.set_filter(&filter.filter) {
+        return bad_request_response(&format!("Invalid log filter: {}", e));
+    }
+    info!(filter = %filter.filter, token = auth.token_name(), "Changed log filter");
+    let filter = LogFilter {
+        filter: server.logging.filter(),
+    };
+    (StatusCode::OK, Json(filter)).into_response()
+}
+
+/// Endpoint streaming the recent and new JSON log lines as server-sent events
+pub async fn log_stream(
+    Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
+) -> Response {
+    if !auth.has_scope(Scope::Admin) {
+        return bad_request_response("Not authorized to stream logs");
+    }
+    Sse::new(log_events(&server))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn log_events(server: &ServerOptions) -> impl Stream<Item = Result<Event, Infallible>> {
+    let stream = server.logging.stream();
+    // Subscribe before reading the recent lines so that none are missed in between
+    let receiver = stream.subscribe();
+    let recent = stream::iter(stream.recent());
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(line) => return Some((line, receiver)),
+                // Slow clients skip the lines they could not keep up with
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    recent
+        .chain(live)
+        .map(|line| Ok(Event::default().data(line)))
+}
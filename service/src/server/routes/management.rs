@@ -0,0 +1,50 @@
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::server::{
+    auth::{Authenticated, Scope},
+    ServerOptions,
+};
+
+use super::bad_request_response;
+
+#[derive(Debug, Deserialize)]
+pub struct IndexingRulesQuery {
+    deployment: Option<String>,
+}
+
+/// Endpoint for the indexing rules of the indexer agent, or the rule applying to a
+/// deployment with `?deployment=`
+pub async fn indexing_rules(
+    Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
+    Query(query): Query<IndexingRulesQuery>,
+) -> Response {
+    if !auth.has_scope(Scope::Admin) {
+        return bad_request_response("Not authorized to read the indexing rules");
+    }
+    let client = &server.indexer_management_client;
+    match query.deployment {
+        Some(deployment) => {
+            (StatusCode::OK, Json(client.indexing_rule(&deployment))).into_response()
+        }
+        None => (StatusCode::OK, Json(client.indexing_rules())).into_response(),
+    }
+}
+
+/// Endpoint for the actions queued for the indexer agent
+pub async fn actions(Extension(server): Extension<ServerOptions>, auth: Authenticated) -> Response {
+    if !auth.has_scope(Scope::Admin) {
+        return bad_request_response("Not authorized to read the actions");
+    }
+    (
+        StatusCode::OK,
+        Json(server.indexer_management_client.actions()),
+    )
+        .into_response()
+}
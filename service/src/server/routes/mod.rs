@@ -1,13 +1,22 @@
-use crate::common::indexer_error::{IndexerError, IndexerErrorCause};
+use crate::{
+    common::indexer_error::{IndexerError, IndexerErrorCause},
+    query_processor::QueryResult,
+};
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use futures::{stream, Future, StreamExt};
 use hyper::http::HeaderName;
+use serde_json::Value;
 
 pub mod basic;
+pub mod cost;
+pub mod logs;
+pub mod management;
 pub mod network;
+pub mod signers;
 pub mod status;
 pub mod subgraphs;
 
@@ -37,3 +46,268 @@ pub fn bad_request_response(error_body: &str) -> Response {
     )
         .into_response()
 }
+
+/// Reject a query with a GraphQL error, before it reaches graph-node
+pub fn graphql_error_response(message: &str) -> Response {
+    graphql_error_response_with_status(StatusCode::BAD_REQUEST, message)
+}
+
+/// GraphQL error with a status other than `400 Bad Request`, such as for timeouts
+pub fn graphql_error_response_with_status(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        axum::response::AppendHeaders([(HeaderName::from_static("graph-attestable"), "false")]),
+        Json(serde_json::json!({ "errors": [{ "message": message }] })),
+    )
+        .into_response()
+}
+
+/// Media type of GraphQL-over-HTTP responses
+pub const GRAPHQL_RESPONSE_MEDIA_TYPE: &str = "application/graphql-response+json";
+
+/// Whether the client accepts GraphQL-over-HTTP responses
+fn accepts_graphql_response(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| {
+            media_type.split(';').next().map(str::trim) == Some(GRAPHQL_RESPONSE_MEDIA_TYPE)
+        })
+}
+
+/// Response of the POST routes that predate GraphQL-over-HTTP, with the GraphQL result
+/// encoded as a JSON string as they always answered. Clients accepting
+/// `application/graphql-response+json` get the result as is instead.
+pub fn encoded_graphql_response(headers: &HeaderMap, body: String, attestable: bool) -> Response {
+    if accepts_graphql_response(headers) {
+        return graphql_response(headers, body, attestable);
+    }
+    (
+        StatusCode::OK,
+        axum::response::AppendHeaders([(
+            HeaderName::from_static("graph-attestable"),
+            if attestable { "true" } else { "false" },
+        )]),
+        Json(body),
+    )
+        .into_response()
+}
+
+/// Response with a GraphQL result from graph-node, passed on as is with the content type
+/// negotiated through the `Accept` header of the request
+pub fn graphql_response(headers: &HeaderMap, body: String, attestable: bool) -> Response {
+    let content_type = if accepts_graphql_response(headers) {
+        GRAPHQL_RESPONSE_MEDIA_TYPE
+    } else {
+        "application/json"
+    };
+    // Replacing the `text/plain` content type of string bodies
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type),
+            (
+                HeaderName::from_static("graph-attestable"),
+                if attestable { "true" } else { "false" },
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Response to a paid query, with the GraphQL result and its attestation, `null` if the
+/// result is not attestable
+pub fn paid_query_response(result: QueryResult) -> Response {
+    (
+        StatusCode::OK,
+        axum::response::AppendHeaders([(
+            HeaderName::from_static("graph-attestable"),
+            if result.attestable { "true" } else { "false" },
+        )]),
+        Json(serde_json::json!({
+            "graphQLResponse": result.graphql_response,
+            "attestation": result.attestation,
+        })),
+    )
+        .into_response()
+}
+
+/// Failure of a single GraphQL operation
+#[derive(Debug, thiserror::Error)]
+pub enum OperationError {
+    /// Rejected before reaching graph-node
+    #[error("{0}")]
+    Invalid(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl IntoResponse for OperationError {
+    fn into_response(self) -> Response {
+        match self {
+            OperationError::Invalid(e) => graphql_error_response(&e),
+            OperationError::Failed(e) => bad_request_response(&e),
+        }
+    }
+}
+
+/// Most operations accepted in a batched request
+pub const MAX_BATCH_OPERATIONS: usize = 100;
+/// Operations of a batch forwarded to graph-node at the same time
+const BATCH_CONCURRENCY: usize = 8;
+
+/// Operations of a batched GraphQL request, a JSON array of requests, or `None` if the body
+/// is a single request
+pub fn batch_operations(body: &str) -> Result<Option<Vec<String>>, String> {
+    if !body.trim_start().starts_with('[') {
+        return Ok(None);
+    }
+    let operations: Vec<Value> =
+        serde_json::from_str(body).map_err(|e| format!("Invalid batched request: {}", e))?;
+    if operations.is_empty() || operations.len() > MAX_BATCH_OPERATIONS {
+        return Err(format!(
+            "Batched requests must have between 1 and {} operations",
+            MAX_BATCH_OPERATIONS
+        ));
+    }
+    Ok(Some(
+        operations
+            .iter()
+            .map(|operation| operation.to_string())
+            .collect(),
+    ))
+}
+
+/// Execute the operations of a batch with bounded concurrency. Returns the JSON array of
+/// results, in the order of the operations, and whether every result is attestable.
+/// A failed operation gets a GraphQL error as its result instead of failing the batch.
+/// Results of paid batches are shaped like paid query responses, each with its attestation.
+pub async fn execute_batch<F, Fut>(
+    operations: Vec<String>,
+    paid: bool,
+    execute: F,
+) -> (String, bool)
+where
+    F: Fn(usize, String) -> Fut,
+    Fut: Future<Output = Result<QueryResult, String>>,
+{
+    let results: Vec<Result<QueryResult, String>> =
+        stream::iter(operations.into_iter().enumerate())
+            .map(|(index, operation)| execute(index, operation))
+            .buffered(BATCH_CONCURRENCY)
+            .collect()
+            .await;
+
+    let attestable = results
+        .iter()
+        .all(|result| result.as_ref().is_ok_and(|result| result.attestable));
+    let results: Vec<String> = results
+        .into_iter()
+        .map(|result| {
+            let (graphql_response, attestation) = match result {
+                Ok(result) => (result.graphql_response, result.attestation),
+                Err(message) => (
+                    serde_json::json!({ "errors": [{ "message": message }] }).to_string(),
+                    None,
+                ),
+            };
+            if paid {
+                serde_json::json!({
+                    "graphQLResponse": graphql_response,
+                    "attestation": attestation,
+                })
+                .to_string()
+            } else {
+                graphql_response
+            }
+        })
+        .collect();
+    (format!("[{}]", results.join(",")), attestable)
+}
+
+#[cfg(test)]
+mod tests {
+    use native::attestation::{AttestationDomain, AttestationSigner};
+    use secp256k1::SecretKey;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn paid_batches_attest_each_operation() {
+        let domain = AttestationDomain::new(1, [0x42; 20]);
+        let signer = AttestationSigner::new(
+            &domain,
+            SecretKey::from_slice(&[0x11; 32]).unwrap(),
+            [0; 32],
+        );
+        let operations = vec![
+            "first".to_string(),
+            "second".to_string(),
+            "failed".to_string(),
+        ];
+
+        let (body, attestable) = execute_batch(operations, true, |_, operation| {
+            let signer = &signer;
+            async move {
+                if operation == "failed" {
+                    return Err("Bad response from Graph node".to_string());
+                }
+                let graphql_response = format!(r#"{{"data":"{}"}}"#, operation);
+                Ok(QueryResult {
+                    attestation: Some(signer.create_attestation(&operation, &graphql_response)),
+                    graphql_response,
+                    attestable: true,
+                })
+            }
+        })
+        .await;
+        assert!(!attestable);
+
+        let results: Vec<Value> = serde_json::from_str(&body).unwrap();
+        for (result, operation) in results.iter().zip(["first", "second"]) {
+            let graphql_response = format!(r#"{{"data":"{}"}}"#, operation);
+            assert_eq!(result["graphQLResponse"], graphql_response.as_str());
+            let attestation = signer.create_attestation(operation, &graphql_response);
+            assert_eq!(
+                result["attestation"],
+                serde_json::to_value(attestation).unwrap()
+            );
+        }
+        assert_eq!(
+            results[2]["graphQLResponse"],
+            r#"{"errors":[{"message":"Bad response from Graph node"}]}"#
+        );
+        assert_eq!(results[2]["attestation"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn post_routes_encode_results_unless_negotiated() {
+        let result = r#"{"data":{"_meta":{"block":{"number":1}}}}"#;
+        let body = |response: Response| async {
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let response = encoded_graphql_response(&HeaderMap::new(), result.to_string(), true);
+        assert_eq!(response.headers()["graph-attestable"], "true");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(body(response).await, serde_json::to_string(result).unwrap());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            "application/graphql-response+json, application/json;q=0.9"
+                .parse()
+                .unwrap(),
+        );
+        let response = encoded_graphql_response(&headers, result.to_string(), true);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            GRAPHQL_RESPONSE_MEDIA_TYPE
+        );
+        assert_eq!(body(response).await, result);
+    }
+}
@@ -1,60 +1,75 @@
-use axum::{
-    extract::Extension,
-    http::{self, Request, StatusCode},
-    response::IntoResponse,
-    Json,
-};
-use hyper::http::HeaderName;
+use axum::{extract::Extension, http::Request, response::IntoResponse};
 
-use crate::server::ServerOptions;
+use crate::{
+    query_processor::UnattestedQueryResult,
+    server::{
+        auth::{Authenticated, Scope},
+        ServerOptions,
+    },
+};
 
-use super::{bad_request_response, response_body_to_query_string};
+use super::{
+    bad_request_response, batch_operations, encoded_graphql_response, execute_batch,
+    graphql_error_response, response_body_to_query_string, OperationError,
+};
 
 pub async fn network_queries(
     Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
     req: Request<axum::body::Body>,
 ) -> impl IntoResponse {
-    // Extract free query auth token
-    let auth_token = req
-        .headers()
-        .get(http::header::AUTHORIZATION)
-        .and_then(|t| t.to_str().ok());
-
-    // Serve only if enabled by indexer and request auth token matches
-    if !(server.serve_network_subgraph
-        && auth_token.is_some()
-        && server.network_subgraph_auth_token.is_some()
-        && auth_token.unwrap() == server.network_subgraph_auth_token.as_deref().unwrap())
-    {
+    // Serve only if enabled by indexer and the request token has the network scope
+    if !(server.serve_network_subgraph && auth.has_scope(Scope::Network)) {
         return bad_request_response("Not enabled or authorized query");
     }
 
     // Serve query using query processor
-    let req_body = req.into_body();
+    let (parts, req_body) = req.into_parts();
+    let headers = parts.headers;
     let query_string = match response_body_to_query_string(req_body).await {
         Ok(q) => q,
         Err(e) => return bad_request_response(&e.to_string()),
     };
+    let operations = match batch_operations(&query_string) {
+        Ok(operations) => operations,
+        Err(e) => return graphql_error_response(&e),
+    };
 
-    let request = server
+    match operations {
+        None => match execute_operation(&server, query_string).await {
+            Ok(result) => encoded_graphql_response(&headers, result.graphql_response, false),
+            Err(e) => e.into_response(),
+        },
+        Some(operations) => {
+            let server = &server;
+            let (body, _) = execute_batch(operations, false, |_, operation| async move {
+                execute_operation(server, operation)
+                    .await
+                    .map(Into::into)
+                    .map_err(|e| e.to_string())
+            })
+            .await;
+            encoded_graphql_response(&headers, body, false)
+        }
+    }
+}
+
+async fn execute_operation(
+    server: &ServerOptions,
+    query_string: String,
+) -> Result<UnattestedQueryResult, OperationError> {
+    if let Err(e) = server.network_query_limits.validate(&query_string) {
+        return Err(OperationError::Invalid(e.to_string()));
+    }
+
+    match server
         .query_processor
         .execute_network_free_query(query_string)
         .await
-        .expect("Failed to execute free network subgraph query");
-
-    match request.status {
-        200 => {
-            let response_body = request.result.graphql_response;
-            (
-                StatusCode::OK,
-                axum::response::AppendHeaders([(
-                    HeaderName::from_static("graph-attestable"),
-                    "false",
-                )]),
-                Json(response_body),
-            )
-                .into_response()
-        }
-        _ => bad_request_response("Bad response from Graph node"),
+    {
+        Ok(res) if res.status == 200 => Ok(res.result),
+        _ => Err(OperationError::Failed(
+            "Bad response from Graph node".to_string(),
+        )),
     }
 }
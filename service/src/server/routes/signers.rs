@@ -0,0 +1,103 @@
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use ethers_core::utils::hex;
+use native::signature_verification::AuthorizedSigner;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    config::{parse_client_signer, ConfigError},
+    server::{
+        auth::{Authenticated, Scope},
+        ServerOptions,
+    },
+};
+
+use super::bad_request_response;
+
+/// Client signers as `address[:valid_from[:valid_until]]`, like in the configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientSigners {
+    signers: Vec<String>,
+}
+
+impl ClientSigners {
+    fn new(signers: Vec<AuthorizedSigner>) -> Self {
+        let timestamp = |timestamp: Option<u64>| timestamp.map(|t| t.to_string());
+        let signers = signers
+            .into_iter()
+            .map(|signer| {
+                let address = format!("0x{}", hex::encode(signer.address));
+                match (timestamp(signer.valid_from), timestamp(signer.valid_until)) {
+                    (None, None) => address,
+                    (from, None) => format!("{}:{}", address, from.unwrap_or_default()),
+                    (from, Some(until)) => {
+                        format!("{}:{}:{}", address, from.unwrap_or_default(), until)
+                    }
+                }
+            })
+            .collect();
+        ClientSigners { signers }
+    }
+}
+
+/// Endpoint for the signers currently trusted to sign query fee receipts
+pub async fn client_signers(
+    Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
+) -> Response {
+    if !auth.has_scope(Scope::Admin) {
+        return bad_request_response("Not authorized to read the client signers");
+    }
+    let signers = ClientSigners::new(server.receipt_verifier.signers());
+    (StatusCode::OK, Json(signers)).into_response()
+}
+
+/// Endpoint to replace the trusted client signers without a restart, e.g. when a gateway
+/// rotates its key
+pub async fn set_client_signers(
+    Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
+    Json(signers): Json<ClientSigners>,
+) -> Response {
+    if !auth.has_scope(Scope::Admin) {
+        return bad_request_response("Not authorized to change the client signers");
+    }
+    let signers = match signers
+        .signers
+        .iter()
+        .map(|signer| parse_client_signer(signer))
+        .collect::<Result<Vec<AuthorizedSigner>, ConfigError>>()
+    {
+        Ok(signers) => signers,
+        Err(e) => return bad_request_response(&e.to_string()),
+    };
+    server.receipt_verifier.set_signers(signers);
+    let signers = ClientSigners::new(server.receipt_verifier.signers());
+    info!(signers = ?signers.signers, token = auth.token_name(), "Changed client signers");
+    (StatusCode::OK, Json(signers)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_signers_are_listed_like_they_are_configured() {
+        let configured = vec![
+            "0xe1ec4339019ec9628438f8755f847e3023e4ff9c".to_string(),
+            "0x1111111111111111111111111111111111111111:1700000000".to_string(),
+            "0x2222222222222222222222222222222222222222::1800000000".to_string(),
+            "0x3333333333333333333333333333333333333333:1700000000:1800000000".to_string(),
+        ];
+        let signers = configured
+            .iter()
+            .map(|signer| parse_client_signer(signer).unwrap())
+            .collect();
+        assert_eq!(ClientSigners::new(signers).signers, configured);
+    }
+}
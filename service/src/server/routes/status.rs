@@ -1,32 +1,48 @@
 use axum::{
     http::{Request, StatusCode},
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Extension, Json,
 };
 
-use reqwest::{header, Client};
+use reqwest::header;
+use serde_json::Value;
 
-use crate::server::ServerOptions;
+use crate::{
+    common::indexer_error::{IndexerError, IndexerErrorCode},
+    server::{
+        auth::{Authenticated, Scope},
+        ServerOptions,
+    },
+    status_query::{check_status_query, PoiRangeRequest},
+};
 
-use super::bad_request_response;
+use super::{bad_request_response, graphql_error_response, response_body_to_query_string};
 
 // Custom middleware function to process the request before reaching the main handler
 pub async fn status_queries(
     Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
     req: Request<axum::body::Body>,
 ) -> impl IntoResponse {
-    let req_body = req.into_body();
-    // TODO: Extract the incoming GraphQL operation and filter root fields
-    // Pass the modified operation to the actual endpoint
+    if server.require_status_auth && !auth.has_scope(Scope::Status) {
+        return bad_request_response("Not authorized status query");
+    }
 
-    let request = Client::new()
-        .post(&server.graph_node_status_endpoint)
-        .body(req_body)
-        .header(header::CONTENT_TYPE, "application/json");
+    let query_string = match response_body_to_query_string(req.into_body()).await {
+        Ok(q) => q,
+        Err(e) => return bad_request_response(&e.to_string()),
+    };
+    // Only pass on allowed root fields, private POIs are for admins
+    if let Err(e) = check_status_query(&query_string, auth.has_scope(Scope::Admin)) {
+        return graphql_error_response(&e.to_string());
+    }
 
-    let response: reqwest::Response = match request.send().await {
+    let response = match status_request(&server, query_string).await {
         Ok(r) => r,
-        Err(e) => return bad_request_response(&e.to_string()),
+        Err(e) => {
+            let e = IndexerError::new(IndexerErrorCode::IE018, Some(e.to_string().into()));
+            return bad_request_response(&e.to_string());
+        }
     };
 
     match response.text().await {
@@ -34,3 +50,47 @@ pub async fn status_queries(
         _ => bad_request_response("Response from Graph node cannot be parsed as a string"),
     }
 }
+
+/// Public POIs for a range of blocks, fetched from graph-node in a single status query
+pub async fn poi_range_queries(
+    Extension(server): Extension<ServerOptions>,
+    auth: Authenticated,
+    Json(request): Json<PoiRangeRequest>,
+) -> Response {
+    if server.require_status_auth && !auth.has_scope(Scope::Status) {
+        return bad_request_response("Not authorized status query");
+    }
+
+    let query_string = match request.to_query() {
+        Ok(q) => q,
+        Err(e) => return graphql_error_response(&e.to_string()),
+    };
+
+    let response = match status_request(&server, query_string).await {
+        Ok(r) => r.text().await,
+        Err(e) => Err(e),
+    };
+    match response
+        .map_err(anyhow::Error::from)
+        .and_then(|r| Ok(serde_json::from_str::<Value>(&r)?))
+    {
+        Ok(r) => (StatusCode::OK, Json(r)).into_response(),
+        Err(e) => {
+            let e = IndexerError::new(IndexerErrorCode::IE019, Some(e.to_string().into()));
+            bad_request_response(&e.to_string())
+        }
+    }
+}
+
+async fn status_request(
+    server: &ServerOptions,
+    body: String,
+) -> Result<reqwest::Response, reqwest::Error> {
+    server
+        .http_client
+        .post(&server.graph_node_status_endpoint)
+        .body(body)
+        .header(header::CONTENT_TYPE, "application/json")
+        .send()
+        .await
+}
@@ -1,27 +1,170 @@
 use axum::{
-    extract::Extension,
-    http::{self, HeaderName, Request, StatusCode},
-    response::IntoResponse,
-    Json,
+    extract::{ws::WebSocketUpgrade, Extension, Path, Query},
+    http::{HeaderMap, Request, StatusCode},
+    response::{IntoResponse, Response},
 };
-use std::sync::Arc;
+use reqwest::{header, Client};
+use serde::Deserialize;
+use serde_json::{json, Value};
 use tracing::trace;
 
 use crate::{
-    query_processor::{FreeQuery, SubgraphDeploymentID},
+    common::indexer_error::{IndexerError, IndexerErrorCode},
+    query_fee::allocations::{receipt_allocation_id, verify_receipt, verify_receipts},
+    query_processor::{FreeQuery, PaidQuery, QueryResult, SubgraphDeploymentID},
     server::{
-        routes::{bad_request_response, response_body_to_query_string},
-        ServerOptions,
+        auth::{Authenticated, Scope},
+        routes::{
+            bad_request_response, batch_operations, encoded_graphql_response, execute_batch,
+            graphql_error_response, graphql_response, paid_query_response,
+            response_body_to_query_string, OperationError,
+        },
+        subscriptions, ServerOptions,
     },
 };
 
+/// Query of a GraphQL-over-HTTP GET request, with URL-encoded parameters
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQLParams {
+    query: String,
+    /// JSON object of the variables
+    variables: Option<String>,
+    operation_name: Option<String>,
+}
+
+impl GraphQLParams {
+    /// Request body equivalent to the parameters, as sent to graph-node
+    fn into_body(self) -> Result<String, String> {
+        let variables = match self.variables.as_deref() {
+            Some(variables) if !variables.is_empty() => serde_json::from_str::<Value>(variables)
+                .map_err(|e| format!("Invalid variables: {}", e))?,
+            _ => Value::Null,
+        };
+        Ok(json!({
+            "query": self.query,
+            "variables": variables,
+            "operationName": self.operation_name,
+        })
+        .to_string())
+    }
+}
+
 pub async fn subgraph_queries(
     Extension(server): Extension<ServerOptions>,
-    id: axum::extract::Path<String>,
+    Path(id): Path<String>,
+    auth: Authenticated,
     req: Request<axum::body::Body>,
-) -> impl IntoResponse {
+) -> Response {
+    let (parts, body) = req.into_parts();
+    let query_string = match response_body_to_query_string(body).await {
+        Ok(q) => q,
+        Err(e) => return bad_request_response(&e.to_string()),
+    };
+    serve_query(
+        &server,
+        &auth,
+        &parts.headers,
+        id,
+        query_string,
+        encoded_graphql_response,
+    )
+    .await
+}
+
+pub async fn subgraph_get_queries(
+    Extension(server): Extension<ServerOptions>,
+    Path(id): Path<String>,
+    auth: Authenticated,
+    headers: HeaderMap,
+    Query(params): Query<GraphQLParams>,
+) -> Response {
+    let query_string = match params.into_body() {
+        Ok(q) => q,
+        Err(e) => return graphql_error_response(&e),
+    };
+    serve_query(&server, &auth, &headers, id, query_string, graphql_response).await
+}
+
+/// Queries addressing a subgraph by name, resolved to its current deployment by graph-node.
+/// Only free queries are served, as receipts are tied to the allocation of a deployment.
+pub async fn subgraph_name_queries(
+    Extension(server): Extension<ServerOptions>,
+    Path(name): Path<String>,
+    auth: Authenticated,
+    req: Request<axum::body::Body>,
+) -> Response {
+    if !auth.has_scope(Scope::FreeQuery) {
+        return bad_request_response("Queries by subgraph name must be authorized free queries");
+    }
+    let (parts, body) = req.into_parts();
+    let query_string = match response_body_to_query_string(body).await {
+        Ok(q) => q,
+        Err(e) => return bad_request_response(&e.to_string()),
+    };
+    serve_name_query(&server, &auth, &parts.headers, &name, query_string).await
+}
+
+pub async fn subgraph_name_get_queries(
+    Extension(server): Extension<ServerOptions>,
+    Path(name): Path<String>,
+    auth: Authenticated,
+    headers: HeaderMap,
+    Query(params): Query<GraphQLParams>,
+) -> Response {
+    if !auth.has_scope(Scope::FreeQuery) {
+        return bad_request_response("Queries by subgraph name must be authorized free queries");
+    }
+    let query_string = match params.into_body() {
+        Ok(q) => q,
+        Err(e) => return graphql_error_response(&e),
+    };
+    serve_name_query(&server, &auth, &headers, &name, query_string).await
+}
+
+async fn serve_name_query(
+    server: &ServerOptions,
+    auth: &Authenticated,
+    headers: &HeaderMap,
+    name: &str,
+    query_string: String,
+) -> Response {
+    // The wildcard captures the leading slash of names like `graphprotocol/graph-network`
+    let name = name.trim_start_matches('/');
+    match resolve_subgraph_name(
+        &server.http_client,
+        &server.graph_node_status_endpoint,
+        name,
+    )
+    .await
+    {
+        Ok(Some(deployment)) => {
+            serve_query(
+                server,
+                auth,
+                headers,
+                deployment,
+                query_string,
+                graphql_response,
+            )
+            .await
+        }
+        Ok(None) => graphql_error_response(&format!("Subgraph `{}` not found", name)),
+        Err(e) => bad_request_response(&e.to_string()),
+    }
+}
+
+/// Serve a query or a batch of queries, with `respond` shaping the free query results
+async fn serve_query(
+    server: &ServerOptions,
+    auth: &Authenticated,
+    headers: &HeaderMap,
+    id: String,
+    query_string: String,
+    respond: fn(&HeaderMap, String, bool) -> Response,
+) -> Response {
     // Extract scalar receipt from header and free query auth token for paid or free query
-    let receipt = if let Some(recipt) = req.headers().get("scalar-receipt") {
+    let receipt = if let Some(recipt) = headers.get("scalar-receipt") {
         match recipt.to_str() {
             Ok(r) => Some(r),
             Err(_) => {
@@ -31,58 +174,247 @@ pub async fn subgraph_queries(
     } else {
         None
     };
-    trace!(
-        "receipt attached by the query, can pass it to TAP: {:?}",
-        receipt
-    );
-
-    // Extract free query auth token
-    let auth_token = req
-        .headers()
-        .get(http::header::AUTHORIZATION)
-        .and_then(|t| t.to_str().ok());
-    // determine if the query is paid or authenticated to be free
-    let free = auth_token.is_some()
-        && server.free_query_auth_token.is_some()
-        && auth_token.unwrap() == server.free_query_auth_token.as_deref().unwrap();
 
-    let query_string = match response_body_to_query_string(req.into_body()).await {
-        Ok(q) => q,
-        Err(e) => return bad_request_response(&e.to_string()),
+    let operations = match batch_operations(&query_string) {
+        Ok(operations) => operations,
+        Err(e) => return graphql_error_response(&e),
     };
 
     // Initialize id into a subgraph deployment ID
-    let subgraph_deployment_id = SubgraphDeploymentID::new(Arc::new(id).to_string());
+    let subgraph_deployment_id = SubgraphDeploymentID::new(id);
 
-    if free {
-        let free_query = FreeQuery {
-            subgraph_deployment_id,
-            query: query_string,
-        };
-        let res = server
-            .query_processor
-            .execute_free_query(free_query)
+    let operations = match operations {
+        Some(operations) => operations,
+        None => {
+            if let Some(receipt) = receipt {
+                let added = match verify_receipt(&server.receipt_verifier, receipt.to_string()) {
+                    Ok(verified) => server.receipt_manager.lock().await.record(verified),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = added {
+                    return bad_request_response(&e.to_string());
+                }
+            }
+            return match execute_operation(
+                server,
+                auth,
+                &subgraph_deployment_id,
+                receipt,
+                query_string,
+            )
             .await
-            .expect("Failed to execute free query");
-
-        match res.status {
-            200 => {
-                let response_body = res.result.graphql_response;
-                let attestable = res.result.attestable;
-                (
-                    StatusCode::OK,
-                    axum::response::AppendHeaders([(
-                        HeaderName::from_static("graph-attestable"),
-                        if attestable { "true" } else { "false" },
-                    )]),
-                    Json(response_body),
-                )
-                    .into_response()
+            {
+                Ok(result) if receipt.is_some() => paid_query_response(result),
+                Ok(result) => respond(headers, result.graphql_response, result.attestable),
+                Err(e) => e.into_response(),
+            };
+        }
+    };
+
+    // Paid batches carry a receipt for each operation, separated by commas, and get an
+    // attestation for each operation
+    let receipts: Option<Vec<&str>> = receipt.map(|r| r.split(',').map(str::trim).collect());
+    if receipts
+        .as_ref()
+        .is_some_and(|receipts| receipts.len() != operations.len())
+    {
+        return bad_request_response("Batched queries need a scalar receipt for each operation");
+    }
+    // Receipts of a batch arrive as a burst, verified together
+    let added: Option<Vec<Result<(), String>>> = match &receipts {
+        Some(receipts) => {
+            let verified = verify_receipts(
+                server.receipt_verifier.clone(),
+                receipts.iter().map(|r| r.to_string()).collect(),
+            )
+            .await;
+            // The receipt manager is only held to record the verified receipts
+            let mut receipt_manager = server.receipt_manager.lock().await;
+            Some(
+                verified
+                    .into_iter()
+                    .map(|verified| {
+                        verified
+                            .and_then(|receipt| receipt_manager.record(receipt))
+                            .map(|_| ())
+                            .map_err(|e| e.to_string())
+                    })
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+    let (subgraph_deployment_id, receipts, added) = (&subgraph_deployment_id, &receipts, &added);
+    let (body, attestable) = execute_batch(operations, receipts.is_some(), |index, operation| {
+        let receipt = receipts.as_ref().map(|receipts| receipts[index]);
+        let added = added.as_ref().map(|added| added[index].clone());
+        async move {
+            if let Some(Err(e)) = added {
+                return Err(e);
             }
-            _ => bad_request_response("Bad response from Graph node"),
+            execute_operation(server, auth, subgraph_deployment_id, receipt, operation)
+                .await
+                .map_err(|e| e.to_string())
         }
-    } else {
-        let error_body = "Query request header missing scalar-receipts or incorrect auth token";
-        bad_request_response(error_body)
+    })
+    .await;
+    respond(headers, body, attestable)
+}
+
+/// Serve a single GraphQL operation, with its own receipt if it is paid. The receipt must
+/// already be added to the receipt manager.
+async fn execute_operation(
+    server: &ServerOptions,
+    auth: &Authenticated,
+    subgraph_deployment_id: &SubgraphDeploymentID,
+    receipt: Option<&str>,
+    query_string: String,
+) -> Result<QueryResult, OperationError> {
+    if let Err(e) = server.free_query_limits.validate(&query_string) {
+        return Err(OperationError::Invalid(e.to_string()));
+    }
+
+    // determine if the query is paid or authenticated to be free
+    if let Some(receipt) = receipt {
+        return execute_paid_operation(server, subgraph_deployment_id, receipt, query_string).await;
+    }
+    if !auth.has_scope(Scope::FreeQuery) {
+        return Err(OperationError::Failed(
+            "Query request header missing scalar-receipts or incorrect auth token".to_string(),
+        ));
+    }
+
+    trace!(token = auth.token_name(), "Serving free query");
+    let free_query = FreeQuery {
+        subgraph_deployment_id: subgraph_deployment_id.clone(),
+        query: query_string,
+    };
+    match server.query_processor.execute_free_query(free_query).await {
+        Ok(res) if res.status == 200 => Ok(res.result.into()),
+        _ => Err(OperationError::Failed(
+            "Bad response from Graph node".to_string(),
+        )),
     }
 }
+
+/// Serve an operation paid for with a verified receipt, if the receipt pays for an active
+/// allocation of the indexer on the deployment
+async fn execute_paid_operation(
+    server: &ServerOptions,
+    subgraph_deployment_id: &SubgraphDeploymentID,
+    receipt: &str,
+    query_string: String,
+) -> Result<QueryResult, OperationError> {
+    let allocation_id = receipt_allocation_id(receipt);
+    let allocation = server
+        .allocation_monitor
+        .allocation(&allocation_id)
+        .filter(|allocation| {
+            subgraph_deployment_id.bytes32().as_deref()
+                == Some(&allocation.subgraph_deployment.id.to_ascii_lowercase())
+        })
+        .ok_or_else(|| {
+            let e = IndexerError::new(
+                IndexerErrorCode::IE063,
+                Some(
+                    format!(
+                        "Allocation {} is not an active allocation on the deployment",
+                        allocation_id
+                    )
+                    .into(),
+                ),
+            );
+            OperationError::Failed(e.to_string())
+        })?;
+    let signer = server
+        .attestation_signers
+        .signer(&allocation)
+        .await
+        .map_err(|e| OperationError::Failed(e.to_string()))?;
+
+    let paid_query = PaidQuery {
+        subgraph_deployment_id: subgraph_deployment_id.clone(),
+        allocation_id,
+        query: query_string,
+    };
+    match server
+        .query_processor
+        .execute_paid_query(paid_query, &signer)
+        .await
+    {
+        Ok(res) if res.status == 200 => Ok(res.result),
+        _ => Err(OperationError::Failed(
+            "Bad response from Graph node".to_string(),
+        )),
+    }
+}
+
+/// GraphQL subscriptions over WebSocket, proxied to graph-node with the graphql-ws protocol.
+/// Only open to free query clients, with a limited number of connections per token.
+pub async fn subgraph_subscriptions(
+    Extension(server): Extension<ServerOptions>,
+    Path(id): Path<String>,
+    auth: Authenticated,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if !auth.has_scope(Scope::FreeQuery) {
+        return bad_request_response("Subscriptions are only open to authorized free queries");
+    }
+    let endpoint = match &server.graph_node_ws_endpoint {
+        Some(endpoint) => endpoint,
+        None => return bad_request_response("Subscriptions are not enabled"),
+    };
+    let guard = match server.subscription_connections.acquire(auth.token_name()) {
+        Some(guard) => guard,
+        None => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many subscription connections for the auth token",
+            )
+                .into_response()
+        }
+    };
+
+    // Connect to graph-node before upgrading, so that failures are reported over HTTP
+    let upstream = match subscriptions::connect_upstream(endpoint, &id).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            return bad_request_response(&format!(
+                "Failed to open subscription connection to graph-node: {}",
+                e
+            ))
+        }
+    };
+    let limits = server.free_query_limits;
+    ws.protocols([subscriptions::GRAPHQL_WS_PROTOCOL])
+        .on_upgrade(move |socket| subscriptions::proxy(socket, upstream, id, limits, guard))
+}
+
+/// Current deployment of a subgraph name, according to the indexing statuses of graph-node
+async fn resolve_subgraph_name(
+    client: &Client,
+    status_endpoint: &str,
+    name: &str,
+) -> Result<Option<String>, IndexerError> {
+    let query = json!({
+        "query": "query deployment($name: String!) { indexingStatusForCurrentVersion(subgraphName: $name) { subgraph } }",
+        "variables": { "name": name },
+    });
+    let error = |e: String| IndexerError::new(IndexerErrorCode::IE018, Some(e.into()));
+    let response = client
+        .post(status_endpoint)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(query.to_string())
+        .send()
+        .await
+        .map_err(|e| error(e.to_string()))?
+        .text()
+        .await
+        .map_err(|e| error(e.to_string()))?;
+    let response: Value = serde_json::from_str(&response).map_err(|e| error(e.to_string()))?;
+
+    Ok(response
+        .pointer("/data/indexingStatusForCurrentVersion/subgraph")
+        .and_then(|deployment| deployment.as_str())
+        .map(str::to_string))
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{net::TcpStream, sync::Mutex as AsyncMutex};
+use tokio_tungstenite::{
+    tungstenite::{
+        self,
+        client::IntoClientRequest,
+        http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue},
+        protocol::{frame::coding::CloseCode, CloseFrame as UpstreamCloseFrame},
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+use tracing::{debug, warn};
+
+use crate::{
+    metrics::{ACTIVE_SUBSCRIPTIONS, SUBSCRIPTION_CONNECTIONS},
+    query_validator::QueryLimits,
+};
+
+/// WebSocket subprotocol spoken by graph-node, from `subscriptions-transport-ws`
+pub const GRAPHQL_WS_PROTOCOL: &str = "graphql-ws";
+
+type Upstream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Subscription connections open for each auth token, bounded by a per-token limit
+#[derive(Debug, Clone)]
+pub struct SubscriptionConnections {
+    limit: usize,
+    open: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl SubscriptionConnections {
+    pub fn new(limit: usize) -> Self {
+        SubscriptionConnections {
+            limit,
+            open: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Reserve a connection for the token, `None` if it already has as many as allowed
+    pub fn acquire(&self, token: &str) -> Option<ConnectionGuard> {
+        let mut open = self.open.lock().unwrap();
+        let count = open.entry(token.to_string()).or_default();
+        if *count >= self.limit {
+            return None;
+        }
+        *count += 1;
+        SUBSCRIPTION_CONNECTIONS.with_label_values(&[token]).inc();
+        Some(ConnectionGuard {
+            connections: self.clone(),
+            token: token.to_string(),
+        })
+    }
+}
+
+/// Connection reserved for a token, released when dropped
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    connections: SubscriptionConnections,
+    token: String,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut open = self.connections.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.token) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.token);
+            }
+        }
+        SUBSCRIPTION_CONNECTIONS
+            .with_label_values(&[&self.token])
+            .dec();
+    }
+}
+
+/// Open a WebSocket to the subscription endpoint of a deployment on graph-node
+pub async fn connect_upstream(
+    endpoint: &str,
+    deployment: &str,
+) -> Result<Upstream, tungstenite::Error> {
+    let mut request = format!(
+        "{}/subgraphs/id/{}",
+        endpoint.trim_end_matches('/'),
+        deployment
+    )
+    .into_client_request()?;
+    request.headers_mut().insert(
+        SEC_WEBSOCKET_PROTOCOL,
+        HeaderValue::from_static(GRAPHQL_WS_PROTOCOL),
+    );
+    let (upstream, _) = tokio_tungstenite::connect_async(request).await?;
+    Ok(upstream)
+}
+
+/// Envelope of graphql-ws messages, enough to follow the lifecycle of subscriptions
+#[derive(Deserialize)]
+struct OperationMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    id: Option<String>,
+    payload: Option<Value>,
+}
+
+/// Subscriptions started and not completed yet on a connection
+struct ActiveSubscriptions {
+    deployment: String,
+    ids: Mutex<HashSet<String>>,
+}
+
+impl ActiveSubscriptions {
+    fn started(&self, id: String) {
+        if self.ids.lock().unwrap().insert(id) {
+            ACTIVE_SUBSCRIPTIONS
+                .with_label_values(&[&self.deployment])
+                .inc();
+        }
+    }
+
+    fn completed(&self, id: &str) {
+        if self.ids.lock().unwrap().remove(id) {
+            ACTIVE_SUBSCRIPTIONS
+                .with_label_values(&[&self.deployment])
+                .dec();
+        }
+    }
+
+    /// Follow a message from the client (`from_client`) or from graph-node
+    fn observe(&self, text: &str, from_client: bool) {
+        let message = match serde_json::from_str::<OperationMessage>(text) {
+            Ok(OperationMessage {
+                message_type,
+                id: Some(id),
+                ..
+            }) => (message_type, id),
+            _ => return,
+        };
+        match (message.0.as_str(), from_client) {
+            ("start" | "subscribe", true) => self.started(message.1),
+            ("stop" | "complete", true) | ("complete" | "error", false) => {
+                self.completed(&message.1)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Drop for ActiveSubscriptions {
+    fn drop(&mut self) {
+        let remaining = self.ids.lock().unwrap().len() as i64;
+        ACTIVE_SUBSCRIPTIONS
+            .with_label_values(&[&self.deployment])
+            .sub(remaining);
+    }
+}
+
+/// graphql-ws `error` message rejecting a subscription whose query exceeds the limits,
+/// `None` for messages that may be relayed
+fn reject(text: &str, limits: &QueryLimits) -> Option<String> {
+    let message = serde_json::from_str::<OperationMessage>(text).ok()?;
+    let payload = match (message.message_type.as_str(), &message.payload) {
+        ("start" | "subscribe", Some(payload)) => payload,
+        _ => return None,
+    };
+    let error = limits.validate(&payload.to_string()).err()?;
+    Some(
+        json!({
+            "type": "error",
+            "id": message.id,
+            "payload": { "message": error.to_string() },
+        })
+        .to_string(),
+    )
+}
+
+/// Relay messages between the client and graph-node until either side closes. Subscriptions
+/// exceeding the free query limits are rejected without reaching graph-node.
+pub async fn proxy(
+    client: WebSocket,
+    upstream: Upstream,
+    deployment: String,
+    limits: QueryLimits,
+    _guard: ConnectionGuard,
+) {
+    let subscriptions = ActiveSubscriptions {
+        deployment,
+        ids: Mutex::new(HashSet::new()),
+    };
+    let (client_sink, mut client_stream) = client.split();
+    // Written to by both directions, to answer rejected subscriptions
+    let client_sink = AsyncMutex::new(client_sink);
+    let (mut upstream_sink, mut upstream_stream) = upstream.split();
+
+    let to_upstream = async {
+        while let Some(Ok(message)) = client_stream.next().await {
+            let message = match message {
+                Message::Text(text) => {
+                    if let Some(error) = reject(&text, &limits) {
+                        if let Err(e) = client_sink.lock().await.send(Message::Text(error)).await {
+                            debug!(error = %e, "Failed to relay message to the client");
+                            break;
+                        }
+                        continue;
+                    }
+                    subscriptions.observe(&text, true);
+                    tungstenite::Message::Text(text)
+                }
+                Message::Binary(data) => tungstenite::Message::Binary(data),
+                Message::Close(frame) => {
+                    let _ = upstream_sink
+                        .send(tungstenite::Message::Close(frame.map(|frame| {
+                            UpstreamCloseFrame {
+                                code: CloseCode::from(frame.code),
+                                reason: frame.reason,
+                            }
+                        })))
+                        .await;
+                    break;
+                }
+                // Pings are answered by each side of the proxy
+                Message::Ping(_) | Message::Pong(_) => continue,
+            };
+            if let Err(e) = upstream_sink.send(message).await {
+                debug!(error = %e, "Failed to relay message to graph-node");
+                break;
+            }
+        }
+    };
+
+    let to_client = async {
+        while let Some(message) = upstream_stream.next().await {
+            let message = match message {
+                Ok(tungstenite::Message::Text(text)) => {
+                    subscriptions.observe(&text, false);
+                    Message::Text(text)
+                }
+                Ok(tungstenite::Message::Binary(data)) => Message::Binary(data),
+                Ok(tungstenite::Message::Close(frame)) => {
+                    let _ = client_sink
+                        .lock()
+                        .await
+                        .send(Message::Close(frame.map(|frame| CloseFrame {
+                            code: frame.code.into(),
+                            reason: frame.reason,
+                        })))
+                        .await;
+                    break;
+                }
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!(error = %e, "Subscription connection to graph-node failed");
+                    break;
+                }
+            };
+            if let Err(e) = client_sink.lock().await.send(message).await {
+                debug!(error = %e, "Failed to relay message to the client");
+                break;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = to_upstream => {}
+        _ = to_client => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriptions_over_the_limits_are_rejected() {
+        let limits: QueryLimits = "first=100".parse().unwrap();
+        let start = |query: &str| {
+            json!({ "type": "start", "id": "1", "payload": { "query": query } }).to_string()
+        };
+
+        let error: Value = serde_json::from_str(
+            &reject(
+                &start("subscription { tokens(first: 1000) { id } }"),
+                &limits,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["id"], "1");
+        assert!(error["payload"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("exceeds the limit of 100"));
+
+        assert_eq!(
+            reject(&start("subscription { tokens(first: 10) { id } }"), &limits),
+            None
+        );
+        assert_eq!(
+            reject(&json!({ "type": "stop", "id": "1" }).to_string(), &limits),
+            None
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use graphql_parser::query::{Definition, OperationDefinition, Selection, SelectionSet};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Root fields of the indexing status API served on `/status`
+const PUBLIC_ROOT_FIELDS: &[&str] = &[
+    "indexingStatuses",
+    "indexingStatusesForSubgraphName",
+    "indexingStatusForCurrentVersion",
+    "indexingStatusForPendingVersion",
+    "publicProofsOfIndexing",
+    "blockData",
+    "blockHashFromNumber",
+    "entityChangesInBlock",
+    "cachedEthereumCalls",
+    "subgraphFeatures",
+    "apiVersions",
+    "__typename",
+    "__schema",
+    "__type",
+];
+
+/// Root fields revealing the indexer's private proofs of indexing, served to admin tokens only
+const ADMIN_ROOT_FIELDS: &[&str] = &["proofOfIndexing"];
+
+/// Most blocks a single POI range request may cover
+pub const MAX_POI_RANGE_BLOCKS: u64 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StatusQueryError {
+    #[error("Invalid status query: {0}")]
+    InvalidRequest(String),
+    #[error("Only queries are supported by the status API")]
+    NotAQuery,
+    #[error("Field `{0}` is not available through the status API")]
+    NotAllowed(String),
+    #[error("Field `{0}` requires an admin token")]
+    AdminOnly(String),
+    #[error("Invalid POI range: {0}")]
+    InvalidRange(String),
+}
+
+/// Check that a status query request body `{ "query": ..., "variables": ... }` only selects
+/// allowed root fields, with `proofOfIndexing` only allowed for admins
+pub fn check_status_query(body: &str, admin: bool) -> Result<(), StatusQueryError> {
+    let body: Value =
+        serde_json::from_str(body).map_err(|e| StatusQueryError::InvalidRequest(e.to_string()))?;
+    let query = body
+        .get("query")
+        .and_then(|query| query.as_str())
+        .ok_or_else(|| StatusQueryError::InvalidRequest("Missing query".to_string()))?;
+    let document = graphql_parser::parse_query::<&str>(query)
+        .map_err(|e| StatusQueryError::InvalidRequest(e.to_string()))?;
+
+    let fragments: Vec<_> = document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::Fragment(fragment) => Some((fragment.name, &fragment.selection_set)),
+            _ => None,
+        })
+        .collect();
+    for definition in &document.definitions {
+        let selection_set = match definition {
+            Definition::Operation(OperationDefinition::SelectionSet(set)) => set,
+            Definition::Operation(OperationDefinition::Query(query)) => &query.selection_set,
+            Definition::Operation(_) => return Err(StatusQueryError::NotAQuery),
+            Definition::Fragment(_) => continue,
+        };
+        check_root_fields(selection_set, &fragments, admin, &mut Vec::new())?;
+    }
+    Ok(())
+}
+
+fn check_root_fields<'a>(
+    selection_set: &'a SelectionSet<'a, &'a str>,
+    fragments: &[(&'a str, &'a SelectionSet<'a, &'a str>)],
+    admin: bool,
+    spreads: &mut Vec<&'a str>,
+) -> Result<(), StatusQueryError> {
+    for selection in &selection_set.items {
+        match selection {
+            Selection::Field(field) => {
+                if ADMIN_ROOT_FIELDS.contains(&field.name) {
+                    if !admin {
+                        return Err(StatusQueryError::AdminOnly(field.name.to_string()));
+                    }
+                } else if !PUBLIC_ROOT_FIELDS.contains(&field.name) {
+                    return Err(StatusQueryError::NotAllowed(field.name.to_string()));
+                }
+            }
+            Selection::InlineFragment(fragment) => {
+                check_root_fields(&fragment.selection_set, fragments, admin, spreads)?
+            }
+            Selection::FragmentSpread(spread) => {
+                if spreads.contains(&spread.fragment_name) {
+                    continue;
+                }
+                if let Some((_, fragment)) = fragments
+                    .iter()
+                    .find(|(name, _)| *name == spread.fragment_name)
+                {
+                    spreads.push(spread.fragment_name);
+                    check_root_fields(fragment, fragments, admin, spreads)?;
+                    spreads.pop();
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Public POIs of a deployment for every `step` blocks from `start_block` to `end_block`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoiRangeRequest {
+    pub deployment: String,
+    pub start_block: u64,
+    pub end_block: u64,
+    pub step: Option<u64>,
+}
+
+impl PoiRangeRequest {
+    fn blocks(&self) -> Result<impl Iterator<Item = u64>, StatusQueryError> {
+        let step = self.step.unwrap_or(1);
+        if step == 0 {
+            return Err(StatusQueryError::InvalidRange(
+                "step must be positive".to_string(),
+            ));
+        }
+        if self.end_block < self.start_block {
+            return Err(StatusQueryError::InvalidRange(
+                "endBlock is before startBlock".to_string(),
+            ));
+        }
+        let count = (self.end_block - self.start_block) / step + 1;
+        if count > MAX_POI_RANGE_BLOCKS {
+            return Err(StatusQueryError::InvalidRange(format!(
+                "{} blocks requested, at most {} are allowed",
+                count, MAX_POI_RANGE_BLOCKS
+            )));
+        }
+        Ok((self.start_block..=self.end_block).step_by(step as usize))
+    }
+
+    /// Status query body requesting every POI of the range in a single
+    /// `publicProofsOfIndexing` call
+    pub fn to_query(&self) -> Result<String, StatusQueryError> {
+        let requests: Vec<Value> = self
+            .blocks()?
+            .map(|block| {
+                json!({
+                    "deployment": self.deployment,
+                    "blockNumber": block.to_string(),
+                })
+            })
+            .collect();
+        Ok(json!({
+            "query": "query publicProofsOfIndexing($requests: [PublicProofOfIndexingRequest!]!) { publicProofsOfIndexing(requests: $requests) { deployment proofOfIndexing block { number } } }",
+            "variables": { "requests": requests },
+        })
+        .to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(query: &str, admin: bool) -> Result<(), StatusQueryError> {
+        check_status_query(&json!({ "query": query }).to_string(), admin)
+    }
+
+    fn range(start_block: u64, end_block: u64, step: Option<u64>) -> PoiRangeRequest {
+        PoiRangeRequest {
+            deployment: "QmVhiE4nax9i86UBnBmQCYDzvjWuwHShYh7aspGPQhU5Sj".to_string(),
+            start_block,
+            end_block,
+            step,
+        }
+    }
+
+    #[test]
+    fn private_pois_are_only_served_to_admins() {
+        let queries = [
+            "{ proofOfIndexing(subgraph: \"Qm\", blockNumber: 1) }",
+            "{ ...poi } fragment poi on Query { proofOfIndexing(subgraph: \"Qm\", blockNumber: 1) }",
+            "{ ... on Query { proofOfIndexing(subgraph: \"Qm\", blockNumber: 1) } }",
+            "query { ...statuses }
+             fragment statuses on Query { indexingStatuses { subgraph } ...nested }
+             fragment nested on Query { ... on Query { ...poi } }
+             fragment poi on Query { proofOfIndexing(subgraph: \"Qm\", blockNumber: 1) }",
+            "{ ...a }
+             fragment a on Query { ...b }
+             fragment b on Query { ...a proofOfIndexing(subgraph: \"Qm\", blockNumber: 1) }",
+        ];
+        for query in queries {
+            assert!(
+                matches!(check(query, false), Err(StatusQueryError::AdminOnly(field)) if field == "proofOfIndexing"),
+                "{}",
+                query
+            );
+            assert!(check(query, true).is_ok(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn public_root_fields_are_allowed() {
+        assert!(check(
+            "{ indexingStatuses { subgraph } ...pois } fragment pois on Query { publicProofsOfIndexing(requests: []) { proofOfIndexing } }",
+            false
+        )
+        .is_ok());
+        // Recursive spreads are checked once and left to graph-node to reject
+        assert!(check(
+            "{ ...a } fragment a on Query { ...a indexingStatuses { subgraph } }",
+            false
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn unknown_root_fields_are_rejected() {
+        for admin in [false, true] {
+            assert!(matches!(
+                check("{ indexingStatuses { subgraph } subgraphs { id } }", admin),
+                Err(StatusQueryError::NotAllowed(field)) if field == "subgraphs"
+            ));
+            assert!(matches!(
+                check("{ ...f } fragment f on Query { ... on Query { allocations { id } } }", admin),
+                Err(StatusQueryError::NotAllowed(field)) if field == "allocations"
+            ));
+        }
+    }
+
+    #[test]
+    fn mutations_and_subscriptions_are_rejected() {
+        for query in [
+            "mutation { indexingStatuses { subgraph } }",
+            "subscription { indexingStatuses { subgraph } }",
+            "{ indexingStatuses { subgraph } } mutation reassign { reassign(subgraph: \"Qm\") }",
+        ] {
+            assert!(
+                matches!(check(query, true), Err(StatusQueryError::NotAQuery)),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn poi_ranges_are_bounded() {
+        assert!(matches!(
+            range(10, 20, Some(0)).to_query(),
+            Err(StatusQueryError::InvalidRange(_))
+        ));
+        assert!(matches!(
+            range(20, 10, None).to_query(),
+            Err(StatusQueryError::InvalidRange(_))
+        ));
+        assert!(matches!(
+            range(0, MAX_POI_RANGE_BLOCKS, None).to_query(),
+            Err(StatusQueryError::InvalidRange(_))
+        ));
+        assert!(range(0, MAX_POI_RANGE_BLOCKS - 1, None).to_query().is_ok());
+        assert!(range(0, 2 * MAX_POI_RANGE_BLOCKS - 1, Some(2))
+            .to_query()
+            .is_ok());
+
+        let query: Value =
+            serde_json::from_str(&range(10, 20, Some(5)).to_query().unwrap()).unwrap();
+        let blocks: Vec<&str> = query["variables"]["requests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|request| request["blockNumber"].as_str().unwrap())
+            .collect();
+        assert_eq!(blocks, vec!["10", "15", "20"]);
+    }
+}
@@ -0,0 +1,260 @@
+use axum::{
+    http::{
+        header::{HeaderName, HeaderValue},
+        HeaderMap, Request,
+    },
+    middleware::Next,
+    response::Response,
+};
+use tracing::{Instrument, Subscriber};
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+/// W3C trace context header
+const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+
+tokio::task_local! {
+    /// `traceparent` header of the request being served, if it sent one
+    static INCOMING_TRACEPARENT: Option<HeaderValue>;
+}
+
+/// Layer exporting spans to the OTLP collector at `endpoint`, if one is configured
+pub fn otlp_layer<S>(
+    endpoint: Option<&str>,
+) -> Result<Option<Box<dyn Layer<S> + Send + Sync>>, anyhow::Error>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    match endpoint {
+        #[cfg(feature = "otlp")]
+        Some(endpoint) => Ok(Some(otlp::layer(endpoint)?)),
+        #[cfg(not(feature = "otlp"))]
+        Some(_) => Err(anyhow::anyhow!(
+            "An OTLP endpoint is configured but the service was built without the `otlp` feature"
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Flush the spans not exported yet
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    otlp::shutdown();
+}
+
+/// Headers carrying the trace context of the current span to downstream services. Without
+/// spans exported to a collector, the `traceparent` of the incoming request is forwarded.
+pub fn trace_context_headers() -> HeaderMap {
+    #[allow(unused_mut)]
+    let mut headers = HeaderMap::new();
+    #[cfg(feature = "otlp")]
+    otlp::inject(&tracing::Span::current(), &mut headers);
+    if !headers.contains_key(TRACEPARENT) {
+        if let Ok(Some(traceparent)) = INCOMING_TRACEPARENT.try_with(Clone::clone) {
+            headers.insert(TRACEPARENT, traceparent);
+        }
+    }
+    headers
+}
+
+/// Middleware running each request in a span, continuing the trace of the caller when it
+/// sends a `traceparent` header
+pub async fn trace_request<B>(req: Request<B>, next: Next<B>) -> Response {
+    let incoming = req.headers().get(TRACEPARENT).cloned();
+    let traceparent = incoming
+        .as_ref()
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        path = %req.uri().path(),
+        traceparent,
+    );
+    #[cfg(feature = "otlp")]
+    otlp::set_parent(&span, req.headers());
+
+    INCOMING_TRACEPARENT
+        .scope(incoming, next.run(req).instrument(span))
+        .await
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use axum::http::{
+        header::{HeaderName, HeaderValue},
+        HeaderMap,
+    };
+    use opentelemetry::{
+        global,
+        propagation::{Extractor, Injector},
+        sdk::{propagation::TraceContextPropagator, trace, Resource},
+        KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig;
+    use tracing::{Span, Subscriber};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::{registry::LookupSpan, Layer};
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(HeaderName::as_str).collect()
+        }
+    }
+
+    struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+    impl Injector for HeaderInjector<'_> {
+        fn set(&mut self, key: &str, value: String) {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(key.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                self.0.insert(name, value);
+            }
+        }
+    }
+
+    pub fn layer<S>(endpoint: &str) -> Result<Box<dyn Layer<S> + Send + Sync>, anyhow::Error>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+    {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let tracer =
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", "indexer-service"),
+                ])))
+                .install_batch(opentelemetry::runtime::Tokio)?;
+        Ok(tracing_opentelemetry::layer().with_tracer(tracer).boxed())
+    }
+
+    pub fn set_parent(span: &Span, headers: &HeaderMap) {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(headers))
+        });
+        span.set_parent(parent);
+    }
+
+    pub fn inject(span: &Span, headers: &mut HeaderMap) {
+        let context = span.context();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(headers))
+        });
+    }
+
+    pub fn shutdown() {
+        global::shutdown_tracer_provider();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+    const CALLER_SPAN_ID: &str = "b7ad6b7169203331";
+
+    /// `traceparent` that a request would send to graph-node
+    async fn downstream_traceparent(traceparent: Option<&str>) -> String {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    trace_context_headers()
+                        .get(TRACEPARENT)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string()
+                }),
+            )
+            .layer(middleware::from_fn(trace_request));
+        let mut request = Request::builder().uri("/");
+        if let Some(traceparent) = traceparent {
+            request = request.header(TRACEPARENT, traceparent);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn incoming_trace_context_is_forwarded() {
+        let traceparent = format!("00-{}-{}-01", TRACE_ID, CALLER_SPAN_ID);
+        assert_eq!(
+            downstream_traceparent(Some(&traceparent)).await,
+            traceparent
+        );
+        assert_eq!(downstream_traceparent(None).await, "");
+    }
+
+    #[cfg(feature = "otlp")]
+    #[tokio::test]
+    async fn exported_spans_continue_the_trace_of_the_caller() {
+        use std::sync::{Arc, Mutex};
+
+        use futures::future::BoxFuture;
+        use opentelemetry::{
+            global,
+            sdk::{
+                export::trace::{ExportResult, SpanData, SpanExporter},
+                propagation::TraceContextPropagator,
+                trace::TracerProvider,
+            },
+            trace::TracerProvider as _,
+        };
+        use tracing_subscriber::layer::SubscriberExt;
+
+        /// In-process collector of the exported spans
+        #[derive(Debug, Clone, Default)]
+        struct Collector(Arc<Mutex<Vec<SpanData>>>);
+
+        impl SpanExporter for Collector {
+            fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+                self.0.lock().unwrap().extend(batch);
+                Box::pin(async { Ok(()) })
+            }
+        }
+
+        let collector = Collector::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(collector.clone())
+            .build();
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let _subscriber = tracing::subscriber::set_default(
+            tracing_subscriber::registry()
+                .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test"))),
+        );
+
+        let downstream =
+            downstream_traceparent(Some(&format!("00-{}-{}-01", TRACE_ID, CALLER_SPAN_ID))).await;
+        provider.force_flush();
+
+        let spans = collector.0.lock().unwrap();
+        let request = spans.iter().find(|span| span.name == "request").unwrap();
+        assert_eq!(request.span_context.trace_id().to_string(), TRACE_ID);
+        assert_eq!(request.parent_span_id.to_string(), CALLER_SPAN_ID);
+        // graph-node continues the trace from the span of the request
+        assert_eq!(
+            downstream,
+            format!("00-{}-{}-01", TRACE_ID, request.span_context.span_id())
+        );
+    }
+}